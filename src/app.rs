@@ -1,12 +1,26 @@
 // use std::io::prelude::*;
+use std::net::SocketAddr;
 use std::rc::Rc;
 
 use async_trait::async_trait;
+use chrono::{Duration, Local, NaiveDate, NaiveTime, Timelike};
 use clap::ArgMatches;
+use tokio::fs;
 
+use trs::db::Record;
+use trs::error::Error;
+use trs::footpaths;
+use trs::ical;
+use trs::planner::Planner;
+use trs::stop::Stop;
+use trs::timetables::Timetables;
+
+use crate::args;
 use crate::config::Config;
-use crate::timetables::Timetables;
-use crate::ui::Ui;
+#[cfg(feature = "notify")]
+use crate::notify;
+use crate::server::ApiServer;
+use crate::ui::{Ui, Wizard};
 
 pub struct App {}
 
@@ -21,14 +35,11 @@ pub enum ArgumentProcessResult {
 
 #[async_trait]
 pub trait ArgSignal {
-    async fn processs_args(
-        &mut self,
-        args: ArgMatches,
-    ) -> Result<ArgumentProcessResult, Box<dyn std::error::Error>>;
+    async fn processs_args(&mut self, args: ArgMatches) -> Result<ArgumentProcessResult, Error>;
 }
 
 impl App {
-    pub async fn run(args: ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn run(args: ArgMatches) -> Result<(), Error> {
         // Create/get config (also handles first start).
         let mut config = Rc::new(Config::new().await?);
 
@@ -40,13 +51,204 @@ impl App {
             .await?;
 
         if let ArgumentProcessResult::Continue = result {
-            // Always print timetables.
-            // Fetch valid/relevant timetables.
-            let timetables = Timetables::new(config.clone()).await;
-            let departures = timetables.get_departures();
+            let timetables = Timetables::new(args::timetables_config(&args)?);
+            let ui = Ui::new(args.clone())?.with_realtime(config.realtime_providers());
+
+            match args.subcommand() {
+                Some(("timetable", sub_args)) => {
+                    let query = sub_args.get_one::<String>("stop").unwrap();
+                    let stop = config.get_stop(query)?;
+                    let date = args::optional_value::<NaiveDate>(sub_args, "date")?
+                        .unwrap_or_else(|| Local::now().naive_local().date());
+
+                    let departures = timetables.get_day_timetable(stop, date);
+                    ui.print_timetable(stop, date, &departures);
+                }
+                Some(("route", sub_args)) => {
+                    let now = Local::now().naive_local();
+                    let date =
+                        args::optional_value::<NaiveDate>(sub_args, "date")?.unwrap_or(now.date());
+                    let time = match sub_args.get_one::<String>("at") {
+                        Some(time) => NaiveTime::parse_from_str(time, "%H:%M")
+                            .map_err(|_| Error::Input(format!("Invalid time \"{}\".", time)))?,
+                        None => now.time(),
+                    };
+                    let count = args::value::<usize>(sub_args, "count")?;
+
+                    let gtfs = config.get_gtfs_file()?;
+                    let planner = Planner::new(&gtfs, date, &config.walking);
+                    let mut stops = vec![];
+
+                    for arg in ["from", "to"] {
+                        let name = sub_args.get_one::<String>(arg).unwrap();
+                        let found = planner.find_stops(name);
+
+                        if found.is_empty() {
+                            return Err(Error::Input(format!(
+                                "No stop with name \"{}\" was found.",
+                                name
+                            )));
+                        }
+
+                        stops.push(found);
+                    }
+
+                    let itineraries = planner.plan(
+                        &stops[0],
+                        &stops[1],
+                        // Itineraries are planned in whole minutes.
+                        time.num_seconds_from_midnight() / 60 * 60,
+                        count,
+                    );
+                    ui.print_itineraries(date, &itineraries);
+                }
+                Some(("nearby", sub_args)) => {
+                    let place = sub_args.get_one::<String>("place").unwrap();
+                    let walk = args::bounded_value(sub_args, "walk", 0..=args::MAX_NEARBY_WALK)?;
+                    let gtfs = config.get_gtfs_file()?;
+
+                    // Coordinates or position of the stop with the name.
+                    let position = footpaths::parse_coordinates(place)
+                        .or_else(|| {
+                            gtfs.stops
+                                .values()
+                                .filter(|s| s.name.to_lowercase() == place.to_lowercase())
+                                .find_map(|s| footpaths::coordinates(s))
+                        })
+                        .ok_or_else(|| {
+                            Error::Input(format!("No stop with name \"{}\" was found.", place))
+                        })?;
+
+                    let mut stops = vec![];
+
+                    for (stop, _) in footpaths::nearby(
+                        gtfs.stops.values().map(|s| s.as_ref()),
+                        position,
+                        &config.walking,
+                        walk * 60,
+                    ) {
+                        let stop = gtfs.stops[&stop.id].clone();
+                        let terminating_stop =
+                            Wizard::get_terminating_trip_stop_for_stop(&gtfs, stop.clone());
+                        let stop = Stop::new(&gtfs, stop, &terminating_stop)?;
+
+                        // Skip stations and stops without any trips.
+                        if !stop.database.records.is_empty() {
+                            stops.push(stop);
+                        }
+                    }
+
+                    let mut departures = timetables.get_stop_departures(&stops);
+                    ui.output(&mut departures, &mut []).await?;
+                }
+                #[cfg(feature = "notify")]
+                Some(("notify", sub_args)) => {
+                    let query = sub_args.get_one::<String>("stop").unwrap();
+                    let stop = config.get_stop(query)?;
+                    let walk = sub_args.get_one::<String>("walk").unwrap();
+                    let walk = notify::parse_walk(walk).ok_or_else(|| {
+                        Error::Input(format!("Invalid walking time \"{}\".", walk))
+                    })?;
+                    let route = sub_args.get_one::<String>("route").map(String::as_str);
+
+                    notify::watch(&timetables, &ui, stop, route, walk).await?;
+                }
+                Some(("board", sub_args)) => {
+                    let path = sub_args.get_one::<String>("html").unwrap();
+                    let refresh = args::value::<u32>(sub_args, "refresh")?;
+
+                    let mut departures = timetables.get_stop_departures(&config.stops);
+                    let mut connections = timetables.get_connections(&config.connections);
+                    let html = ui.board(&mut departures, &mut connections, refresh).await?;
+
+                    fs::write(path, html).await?;
+                }
+                Some(("export", sub_args)) => {
+                    if let Some(("ical", ical_args)) = sub_args.subcommand() {
+                        let query = ical_args.get_one::<String>("stop").unwrap();
+                        let stop = config.get_stop(query)?;
+                        let route = ical_args.get_one::<String>("route");
+                        let days =
+                            args::bounded_value(ical_args, "days", 1..=args::MAX_EXPORT_DAYS)?;
+                        let today = Local::now().naive_local().date();
+
+                        let calendar = if ical_args.is_present("recurring") {
+                            let mut records = timetables.get_stop_records(stop);
+                            records.retain(|r| route.is_none_or(|route| &r.route == route));
+
+                            ical::recurring_calendar(
+                                stop,
+                                &records,
+                                today,
+                                today + Duration::days(days - 1),
+                            )
+                        } else {
+                            let mut records = (0..days)
+                                .flat_map(|i| {
+                                    timetables.get_day_timetable(stop, today + Duration::days(i))
+                                })
+                                .collect::<Vec<Record>>();
+                            records.retain(|r| route.is_none_or(|route| &r.route == route));
+
+                            ical::departures_calendar(stop, &records)
+                        };
+
+                        match ical_args.get_one::<String>("output") {
+                            Some(path) => fs::write(path, calendar).await?,
+                            None => print!("{}", calendar),
+                        }
+                    }
+                }
+                Some(("serve", sub_args)) => {
+                    let bind = sub_args.get_one::<String>("bind").unwrap();
+                    let address = bind
+                        .parse::<SocketAddr>()
+                        .map_err(|_| Error::Input(format!("Invalid address \"{}\".", bind)))?;
+
+                    ApiServer::new(config.clone(), args.clone())?
+                        .serve(address)
+                        .await?;
+                }
+                Some(("trip", sub_args)) => {
+                    let trip_id = sub_args.get_one::<String>("trip_id").unwrap();
+                    // User's stop or any stop ID.
+                    let stop_id = sub_args.get_one::<String>("stop").map(|query| {
+                        config
+                            .find_stop(query)
+                            .map_or(query.as_str(), |stop| stop.id.as_str())
+                    });
+
+                    let gtfs = config.get_gtfs_file()?;
+                    let trip = Timetables::get_trip(&gtfs, trip_id, stop_id)?;
+                    ui.print_trip(&trip, None);
+                }
+                _ => {
+                    // Always print timetables.
+                    // Fetch valid/relevant timetables.
+                    let mut departures = timetables.get_stop_departures(&config.stops);
+                    let mut connections = timetables.get_connections(&config.connections);
+
+                    // -s argument
+                    if let Some(query) = args.get_one::<String>("stop") {
+                        let stop = config.get_stop(query)?;
+                        departures.retain(|d| std::ptr::eq(d.stop, stop));
+                        connections.clear();
+                    }
+
+                    // Render timetables.
+                    ui.output(&mut departures, &mut connections).await?;
 
-            // Render timetables.
-            Ui::new(args.clone()).output(departures).await;
+                    // -e argument
+                    if args.is_present("expand") {
+                        if let Some((stop, record)) = Ui::select_departure(&departures)? {
+                            let gtfs = config.get_gtfs_file()?;
+                            let trip =
+                                Timetables::get_trip(&gtfs, &record.trip_id, Some(&stop.id))?;
+                            ui.print_trip(&trip, record.service_date);
+                        }
+                    }
+                }
+            }
         }
 
         Ok(())
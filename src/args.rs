@@ -1,12 +1,46 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
 use clap::{App, AppSettings, Arg, ArgMatches};
+use trs::error::Error;
+use trs::timetables::TimetablesConfig;
 
 /// Default limit for departures to be printed out.
 const DEPARTURES_COUNT: &str = "3";
+/// Default number of days to look ahead for departures.
+const HORIZON_DAYS: &str = "7";
+/// Maximum number of days to look ahead for departures.
+const MAX_HORIZON_DAYS: i64 = 366;
+/// Default walking time (minutes) for nearby stops.
+const NEARBY_WALK: &str = "3";
+/// Maximum walking time (minutes) for nearby stops.
+pub const MAX_NEARBY_WALK: u32 = 120;
+/// Default walking time to the stop for notifications.
+#[cfg(feature = "notify")]
+const WALK_TIME: &str = "5m";
+/// Default number of planned itineraries.
+const ITINERARIES_COUNT: &str = "3";
+/// Default refresh interval (seconds) of the HTML departure board.
+const BOARD_REFRESH: &str = "60";
+/// Default number of days exported to calendar.
+const EXPORT_DAYS: &str = "14";
+/// Maximum number of days exported to calendar.
+pub const MAX_EXPORT_DAYS: i64 = 366;
+/// Default address of the HTTP API server.
+const BIND_ADDRESS: &str = "127.0.0.1:8080";
+/// Minutes to look back for departures delayed past their scheduled time.
+const DELAY_LOOKBACK: i64 = 15;
 
 pub fn parse() -> ArgMatches {
-    App::new("TranspoRS")
+    app().get_matches()
+}
+
+/// Builds the command line interface (arguments and subcommands).
+pub fn app() -> App<'static> {
+    let app = App::new("TranspoRS")
         .setting(AppSettings::ColoredHelp)
-        .version("0.1.2")
+        .version(env!("CARGO_PKG_VERSION"))
         .author("Hrdina Pavel <hrdina.pavel@gmail.com>")
         .about("Transportation timetables for command line.")
         .arg(
@@ -24,6 +58,16 @@ pub fn parse() -> ArgMatches {
                 .short('d')
                 .help("Delete one stop from user's stops configuration."),
         )
+        .arg(
+            Arg::with_name("add-connection")
+                .short('c')
+                .help("Adds one connection (origin and destination stop) to user's configuration."),
+        )
+        .arg(
+            Arg::with_name("delete-connection")
+                .short('C')
+                .help("Deletes one connection from user's configuration."),
+        )
         .arg(
             Arg::with_name("wipe")
                 .short('w')
@@ -34,7 +78,320 @@ pub fn parse() -> ArgMatches {
                 .short('l')
                 .takes_value(true)
                 .default_value(DEPARTURES_COUNT)
+                .global(true)
                 .help("Limits number of departures from each stop."),
         )
-        .get_matches()
+        .arg(
+            Arg::with_name("horizon")
+                .long("horizon")
+                .takes_value(true)
+                .default_value(HORIZON_DAYS)
+                .global(true)
+                .help("Number of days to look ahead when there are not enough departures today (up to 366)."),
+        )
+        .arg(
+            Arg::with_name("format")
+                .short('f')
+                .long("format")
+                .takes_value(true)
+                .possible_values(["default", "json", "waybar", "line"])
+                .default_value("default")
+                .global(true)
+                .help("Output format of departures."),
+        )
+        .arg(
+            Arg::with_name("stop")
+                .short('s')
+                .long("stop")
+                .takes_value(true)
+                .help("Shows only the stop with the ID or name (or its part)."),
+        )
+        .arg(
+            Arg::with_name("wheelchair")
+                .long("wheelchair")
+                .global(true)
+                .help("Shows only wheelchair accessible departures."),
+        )
+        .arg(
+            Arg::with_name("bikes")
+                .long("bikes")
+                .global(true)
+                .help("Shows only departures allowing bikes."),
+        )
+        .arg(
+            Arg::with_name("no-realtime")
+                .long("no-realtime")
+                .global(true)
+                .help("Shows scheduled times only (no realtime info is fetched)."),
+        )
+        .arg(
+            Arg::with_name("expand")
+                .short('e')
+                .long("expand")
+                .help("Lets you choose one of the departures and shows all stops of its trip."),
+        )
+        .subcommand(
+            App::new("trip")
+                .about("Prints all stops of a trip with arrival times.")
+                .arg(
+                    Arg::with_name("trip_id")
+                        .required(true)
+                        .help("Trip ID (see JSON output)."),
+                )
+                .arg(
+                    Arg::with_name("stop")
+                        .long("stop")
+                        .takes_value(true)
+                        .help("Stop (ID or name) to print the trip from."),
+                ),
+        )
+        .subcommand(
+            App::new("route")
+                .about("Plans journeys (with transfers) between two stops.")
+                .arg(
+                    Arg::with_name("from")
+                        .required(true)
+                        .help("Origin stop name."),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .required(true)
+                        .help("Destination stop name."),
+                )
+                .arg(
+                    Arg::with_name("at")
+                        .long("at")
+                        .takes_value(true)
+                        .help("Departure time as HH:MM (now by default)."),
+                )
+                .arg(
+                    Arg::with_name("date")
+                        .long("date")
+                        .takes_value(true)
+                        .help("Departure date as YYYY-MM-DD (today by default)."),
+                )
+                .arg(
+                    Arg::with_name("count")
+                        .short('n')
+                        .takes_value(true)
+                        .default_value(ITINERARIES_COUNT)
+                        .help("Number of itineraries to be printed."),
+                ),
+        )
+        .subcommand(
+            App::new("nearby")
+                .about("Prints departures from all stops within walking distance.")
+                .arg(
+                    Arg::with_name("place")
+                        .required(true)
+                        .help("Stop name or coordinates as LAT,LON."),
+                )
+                .arg(
+                    Arg::with_name("walk")
+                        .long("walk")
+                        .takes_value(true)
+                        .default_value(NEARBY_WALK)
+                        .help("Maximum walking time in minutes (up to 120)."),
+                ),
+        )
+        .subcommand(
+            App::new("timetable")
+                .about("Prints full day timetable of one of user's stops.")
+                .arg(
+                    Arg::with_name("stop")
+                        .required(true)
+                        .help("Stop ID or name (or its part)."),
+                )
+                .arg(
+                    Arg::with_name("date")
+                        .long("date")
+                        .takes_value(true)
+                        .help("Date of the timetable as YYYY-MM-DD (today by default)."),
+                ),
+        )
+        .subcommand(
+            App::new("board")
+                .about("Renders departures into auto-refreshing HTML departure board.")
+                .arg(
+                    Arg::with_name("html")
+                        .long("html")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Path of the HTML file to write."),
+                )
+                .arg(
+                    Arg::with_name("refresh")
+                        .long("refresh")
+                        .takes_value(true)
+                        .default_value(BOARD_REFRESH)
+                        .help("Page reload interval in seconds."),
+                ),
+        )
+        .subcommand(
+            App::new("export")
+                .about("Exports departures to other formats.")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    App::new("ical")
+                        .about("Exports departures from the stop as iCalendar (.ics).")
+                        .arg(
+                            Arg::with_name("stop")
+                                .long("stop")
+                                .takes_value(true)
+                                .required(true)
+                                .help("Stop ID or name (or its part)."),
+                        )
+                        .arg(
+                            Arg::with_name("route")
+                                .long("route")
+                                .takes_value(true)
+                                .help("Route (line) name."),
+                        )
+                        .arg(
+                            Arg::with_name("days")
+                                .long("days")
+                                .takes_value(true)
+                                .default_value(EXPORT_DAYS)
+                                .help("Number of days to export (up to 366)."),
+                        )
+                        .arg(Arg::with_name("recurring").long("recurring").help(
+                            "Exports weekly recurring events instead of an event per departure.",
+                        ))
+                        .arg(
+                            Arg::with_name("output")
+                                .short('o')
+                                .long("output")
+                                .takes_value(true)
+                                .help(
+                                    "Path of the .ics file to write (prints to stdout by default).",
+                                ),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("serve")
+                .about("Runs local HTTP server with departures JSON API.")
+                .arg(
+                    Arg::with_name("bind")
+                        .long("bind")
+                        .takes_value(true)
+                        .default_value(BIND_ADDRESS)
+                        .help("Address to listen on."),
+                ),
+        );
+
+    #[cfg(feature = "notify")]
+    let app = app.subcommand(
+        App::new("notify")
+            .about("Runs in background and notifies you when it's time to leave for the next departure.")
+            .arg(
+                Arg::with_name("stop")
+                    .long("stop")
+                    .takes_value(true)
+                    .required(true)
+                    .help("Stop ID or name (or its part)."),
+            )
+            .arg(
+                Arg::with_name("route")
+                    .long("route")
+                    .takes_value(true)
+                    .help("Route (line) name."),
+            )
+            .arg(
+                Arg::with_name("walk")
+                    .long("walk")
+                    .takes_value(true)
+                    .default_value(WALK_TIME)
+                    .help("Walking time to the stop like 6m or 90s (up to 2 hours)."),
+            ),
+    );
+
+    app
+}
+
+/// Builds departures lookup options from arguments.
+pub fn timetables_config(args: &ArgMatches) -> Result<TimetablesConfig, Error> {
+    Ok(TimetablesConfig {
+        // -l argument
+        limit: value(args, "limit")?,
+        // --horizon argument
+        horizon: bounded_value(args, "horizon", 0..=MAX_HORIZON_DAYS)?,
+        // --wheelchair argument
+        wheelchair: args.is_present("wheelchair"),
+        // --bikes argument
+        bikes: args.is_present("bikes"),
+        // Delayed departures only matter with realtime info.
+        lookback: if args.is_present("no-realtime") {
+            0
+        } else {
+            DELAY_LOOKBACK
+        },
+    })
+}
+
+/// Parses value of the argument (with default value or required).
+pub fn value<T: FromStr>(args: &ArgMatches, name: &str) -> Result<T, Error> {
+    optional_value(args, name)?
+        .ok_or_else(|| Error::Input(format!("Argument \"{}\" is missing.", name)))
+}
+
+/// Parses value of the argument (see `value()`) which has to be within
+/// the range.
+pub fn bounded_value<T>(args: &ArgMatches, name: &str, range: RangeInclusive<T>) -> Result<T, Error>
+where
+    T: FromStr + PartialOrd + fmt::Display,
+{
+    let value = value(args, name)?;
+
+    if range.contains(&value) {
+        Ok(value)
+    } else {
+        Err(Error::Input(format!(
+            "Value {} of argument \"{}\" is out of range {}-{}.",
+            value,
+            name,
+            range.start(),
+            range.end()
+        )))
+    }
+}
+
+/// Parses value of the optional argument.
+pub fn optional_value<T: FromStr>(args: &ArgMatches, name: &str) -> Result<Option<T>, Error> {
+    args.get_one::<String>(name)
+        .map(|value| {
+            value.parse().map_err(|_| {
+                Error::Input(format!(
+                    "Invalid value \"{}\" of argument \"{}\".",
+                    value, name
+                ))
+            })
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(horizon: &str) -> ArgMatches {
+        App::new("test")
+            .arg(Arg::with_name("horizon").long("horizon").takes_value(true))
+            .get_matches_from(["test".to_string(), format!("--horizon={}", horizon)])
+    }
+
+    #[test]
+    fn value_within_range() {
+        assert_eq!(bounded_value(&args("7"), "horizon", 0..=366).ok(), Some(7));
+    }
+
+    #[test]
+    fn value_out_of_range() {
+        for horizon in ["200000000", "-1", "99999999999999999999"] {
+            assert!(matches!(
+                bounded_value::<i64>(&args(horizon), "horizon", 0..=366),
+                Err(Error::Input(_))
+            ));
+        }
+    }
 }
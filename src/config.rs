@@ -1,59 +1,77 @@
 use std::io::{self, prelude::*};
 use std::path::PathBuf;
+use std::time::SystemTime;
 
 use async_trait::async_trait;
 use clap::ArgMatches;
-use derivative::Derivative;
 use gtfs_structures::Gtfs;
 use serde::{Deserialize, Serialize};
 use spinners::{Spinner, Spinners};
 use tokio::fs::{self, File};
 use tokio::io::AsyncReadExt;
 
+use trs::db::DataFile;
+use trs::error::Error;
+use trs::footpaths::Walking;
+use trs::realtime::{RealtimeFeed, RealtimeProvider};
+use trs::stop::{Connection, Stop};
+
 use crate::app::{ArgSignal, ArgumentProcessResult};
-use crate::db::{DataFile, Database};
-use crate::ui::{FoundStop, Ui, Wizard};
+use crate::ui::{FoundConnection, FoundStop, Ui, Wizard};
 
 const CONF_DIR: &str = "transpors";
 const CONF_FILE: &str = "config.yaml";
 
-#[derive(Serialize, Deserialize, Derivative)]
-#[derivative(Debug)]
-pub struct Stop {
-    id: String,
-    pub name: String,
-    pub terminating_stop: String,
-    #[derivative(Debug = "ignore")]
-    pub database: Database,
-}
-
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     data_file_url: String,
     data_file_path: PathBuf,
     pub user_stops: Vec<FoundStop>,
     pub stops: Vec<Stop>,
+    #[serde(default)]
+    pub user_connections: Vec<FoundConnection>,
+    #[serde(default)]
+    pub connections: Vec<Connection>,
+    #[serde(default)]
+    pub walking: Walking,
+    /// Realtime feeds of the data file (Golemio with `prague` feature
+    /// by default).
+    #[serde(default = "default_realtime")]
+    pub realtime: Vec<RealtimeFeed>,
+}
+
+/// Golemio provides realtime info for Prague data file users.
+fn default_realtime() -> Vec<RealtimeFeed> {
+    if cfg!(feature = "prague") {
+        vec![RealtimeFeed::golemio()]
+    } else {
+        vec![]
+    }
 }
 
 impl Config {
-    pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    pub async fn new() -> Result<Self, Error> {
         let config: Self;
 
         // Determine config path.
-        let dir = Self::determine_conf_dir();
+        let dir = Self::determine_conf_dir()?;
 
         // Check if config exists.
         if !dir.exists() {
             Self::create_conf_dir(&dir).await?;
             let mut wiz = Wizard::new(&dir).await;
-            let output = wiz.run_wizard().await.expect("Wizard failed. Exiting.");
-            let stops = Self::build_stops_database(&output.gtfs, &output.stops).await;
+            let output = wiz.run_wizard().await?;
+            let stops = Self::build_stops_database(&output.gtfs, &output.stops).await?;
 
             config = Self {
                 data_file_url: wiz.data_file_url.unwrap(),
                 data_file_path: wiz.data_file_path.unwrap(),
                 user_stops: output.stops,
                 stops,
+                user_connections: vec![],
+                connections: vec![],
+                walking: Walking::default(),
+                realtime: default_realtime(),
             };
 
             config.save().await?;
@@ -73,35 +91,89 @@ impl Config {
         Ok(config)
     }
 
+    /// Creates providers of realtime feeds. Feeds of providers which are
+    /// not available in this build are skipped with a warning on stderr
+    /// (stdout may be JSON).
+    pub fn realtime_providers(&self) -> Vec<Box<dyn RealtimeProvider>> {
+        self.realtime
+            .iter()
+            .filter_map(|feed| match feed.provider(Self::determine_cache_dir()) {
+                Ok(provider) => Some(provider),
+                Err(e) => {
+                    eprintln!("Warning: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Finds user's stop by its ID or name (or a part of the name).
+    pub fn find_stop(&self, query: &str) -> Option<&Stop> {
+        let name = query.to_lowercase();
+
+        self.stops
+            .iter()
+            .find(|s| s.id == query || s.name.to_lowercase() == name)
+            .or_else(|| {
+                self.stops
+                    .iter()
+                    .find(|s| s.name.to_lowercase().contains(&name))
+            })
+    }
+
+    /// Finds user's stop like `find_stop()` but reports missing stop as error.
+    pub fn get_stop(&self, query: &str) -> Result<&Stop, Error> {
+        self.find_stop(query)
+            .ok_or_else(|| Error::Input(format!("Stop \"{}\" is not among your stops.", query)))
+    }
+
     /// Loads config file and constructs self.
-    async fn load() -> Result<Self, Box<dyn std::error::Error>> {
+    pub async fn load() -> Result<Self, Error> {
         // Load config file.
-        let mut config_file = File::open(Self::determine_conf_file_path()).await?;
+        let mut config_file = File::open(Self::determine_conf_file_path()?).await?;
         let mut file_content = String::new();
         config_file.read_to_string(&mut file_content).await?;
 
         // Construct Self.
-        Ok(serde_yaml::from_str(&file_content)?)
+        serde_yaml::from_str(&file_content)
+            .map_err(|e| Error::Config(format!("Invalid config file: {}", e)))
+    }
+
+    /// Returns last modification time of the config file (changes whenever
+    /// the database is refreshed or stops are added/deleted).
+    pub fn modified() -> Option<SystemTime> {
+        std::fs::metadata(Self::determine_conf_file_path().ok()?)
+            .and_then(|m| m.modified())
+            .ok()
     }
 
     /// Determines main config ditectory (wrapper for all app files).
-    fn determine_conf_dir() -> PathBuf {
-        let mut dir = dirs::config_dir().expect("Config directory is not available.");
+    fn determine_conf_dir() -> Result<PathBuf, Error> {
+        let mut dir = dirs::config_dir()
+            .ok_or_else(|| Error::Config("Config directory is not available.".to_string()))?;
+        dir.push(CONF_DIR);
+
+        Ok(dir)
+    }
+
+    /// Determines cache directory (realtime responses).
+    fn determine_cache_dir() -> Option<PathBuf> {
+        let mut dir = dirs::cache_dir()?;
         dir.push(CONF_DIR);
 
-        dir
+        Some(dir)
     }
 
     /// Determines main config file path.
-    fn determine_conf_file_path() -> PathBuf {
-        let mut path = Self::determine_conf_dir();
+    fn determine_conf_file_path() -> Result<PathBuf, Error> {
+        let mut path = Self::determine_conf_dir()?;
         path.push(CONF_FILE);
 
-        path
+        Ok(path)
     }
 
     /// Creates config directory and returns path to that directory.
-    async fn create_conf_dir(path: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    async fn create_conf_dir(path: &PathBuf) -> Result<(), Error> {
         if !path.exists() {
             fs::create_dir_all(&path).await?;
         }
@@ -110,43 +182,70 @@ impl Config {
     }
 
     /// Saves config (serialize) to config YAML file.
-    async fn save(&self) -> Result<(), std::io::Error> {
-        fs::write(
-            Self::determine_conf_file_path(),
-            serde_yaml::to_string(self).expect("Couldn't serialize config."),
-        )
-        .await
+    async fn save(&self) -> Result<(), Error> {
+        let content = serde_yaml::to_string(self)
+            .map_err(|e| Error::Config(format!("Couldn't serialize config: {}", e)))?;
+        fs::write(Self::determine_conf_file_path()?, content).await?;
+
+        Ok(())
     }
 
     /// Builds up stop database for each stop from config.
-    async fn build_stops_database(gtfs: &Gtfs, stops: &Vec<FoundStop>) -> Vec<Stop> {
-        let mut processed_stops = vec![];
+    async fn build_stops_database(gtfs: &Gtfs, stops: &[FoundStop]) -> Result<Vec<Stop>, Error> {
         let mut sp = Spinner::new(Spinners::Line, "fetching times...".into());
 
         // TODO: implement rayon
-        for found_stop in stops {
-            // TODO: remove unwrap set up error.
-            let database = Database::from(gtfs, found_stop.stop.clone()).unwrap();
-            processed_stops.push(Stop {
-                id: found_stop.id.clone(),
-                name: found_stop.stop.name.clone(),
-                terminating_stop: found_stop.terminating_stop.name.clone(),
-                database,
-            });
-        }
+        let processed_stops = stops
+            .iter()
+            .map(|found_stop| {
+                Stop::new(gtfs, found_stop.stop.clone(), &found_stop.terminating_stop)
+            })
+            .collect::<Result<Vec<Stop>, Error>>();
 
         sp.stop();
-        println!("done");
+        println!("{}", Self::outcome(&processed_stops));
 
         processed_stops
     }
 
+    /// Builds up trips database for each connection from config.
+    async fn build_connections_database(
+        gtfs: &Gtfs,
+        connections: &[FoundConnection],
+    ) -> Result<Vec<Connection>, Error> {
+        let mut sp = Spinner::new(Spinners::Line, "fetching connections...".into());
+
+        let processed_connections = connections
+            .iter()
+            .map(|found_connection| {
+                Connection::new(
+                    gtfs,
+                    found_connection.from.stop.clone(),
+                    found_connection.to.stop.clone(),
+                )
+            })
+            .collect::<Result<Vec<Connection>, Error>>();
+
+        sp.stop();
+        println!("{}", Self::outcome(&processed_connections));
+
+        processed_connections
+    }
+
+    /// Returns spinner outcome message for the result.
+    fn outcome<T>(result: &Result<T, Error>) -> &'static str {
+        match result {
+            Ok(_) => "done",
+            Err(_) => "failed",
+        }
+    }
+
     /// Downloads or copies (depends on the origin location) the datafile
     /// to project config location (see Config.path) and parses it's content.
     /// Stops database is then rebuilded and saved.
-    async fn refresh_data_file(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    async fn refresh_data_file(&mut self) -> Result<(), Error> {
         // 1. download
-        let df = DataFile::new(&Self::determine_conf_dir(), self.data_file_url.clone());
+        let df = DataFile::new(&Self::determine_conf_dir()?, self.data_file_url.clone());
 
         // Spinner - start.
         let mut sp = Spinner::new(Spinners::Line, "retrieving...".into());
@@ -170,7 +269,9 @@ impl Config {
         println!("done");
 
         // 3. build database.
-        self.stops = Config::build_stops_database(&gtfs, &self.user_stops).await;
+        self.stops = Config::build_stops_database(&gtfs, &self.user_stops).await?;
+        self.connections =
+            Config::build_connections_database(&gtfs, &self.user_connections).await?;
 
         // 4. Save config.
         self.save().await?;
@@ -179,20 +280,22 @@ impl Config {
     }
 
     /// Removed whole config directory.
-    async fn wipe(&self) -> Result<(), std::io::Error> {
-        fs::remove_dir_all(Self::determine_conf_dir()).await
+    async fn wipe(&self) -> Result<(), Error> {
+        fs::remove_dir_all(Self::determine_conf_dir()?).await?;
+
+        Ok(())
     }
 
     /// Loads existing (already downloaded) GTFS data file
     /// while showing loading spinners.
-    fn get_gtfs_file(&self) -> Result<Gtfs, Box<dyn std::error::Error>> {
-        let conf_dir = Self::determine_conf_dir();
+    pub fn get_gtfs_file(&self) -> Result<Gtfs, Error> {
+        let conf_dir = Self::determine_conf_dir()?;
         let df = DataFile::new(&conf_dir, self.data_file_url.clone());
         let mut sp = Spinner::new(
             Spinners::Line,
             "parsing data file (can take minutes)...".into(),
         );
-        io::stdout().flush().unwrap();
+        io::stdout().flush()?;
         let gtfs = df.parse()?;
         sp.stop();
         println!("done");
@@ -207,12 +310,10 @@ impl ArgSignal for Config {
     /// -r
     /// -a
     /// -d
+    /// -c
+    /// -C
     /// -w
-    /// -l
-    async fn processs_args(
-        &mut self,
-        args: ArgMatches,
-    ) -> Result<ArgumentProcessResult, Box<dyn std::error::Error>> {
+    async fn processs_args(&mut self, args: ArgMatches) -> Result<ArgumentProcessResult, Error> {
         // -r argument
         if args.is_present("refresh") {
             self.refresh_data_file().await?;
@@ -221,7 +322,7 @@ impl ArgSignal for Config {
         // -a argument
         if args.is_present("add-stop") {
             // 1. parse GTFS file.
-            let conf_dir = Self::determine_conf_dir();
+            let conf_dir = Self::determine_conf_dir()?;
             let gtfs = self.get_gtfs_file()?;
 
             // 2. read new stops.
@@ -230,7 +331,7 @@ impl ArgSignal for Config {
             self.user_stops.append(&mut wiz.read_stop_names(&gtfs)?);
 
             // 3. build stop database.
-            self.stops = Self::build_stops_database(&gtfs, &self.user_stops).await;
+            self.stops = Self::build_stops_database(&gtfs, &self.user_stops).await?;
 
             // 4. save config
             self.save().await?;
@@ -255,7 +356,7 @@ impl ArgSignal for Config {
                     Ui::info(&msg);
 
                     // 4. build stop database.
-                    self.stops = Self::build_stops_database(&gtfs, &self.user_stops).await;
+                    self.stops = Self::build_stops_database(&gtfs, &self.user_stops).await?;
                     self.save().await?;
 
                     break;
@@ -263,6 +364,41 @@ impl ArgSignal for Config {
             }
         }
 
+        // -c argument
+        if args.is_present("add-connection") {
+            // 1. parse GTFS file.
+            let conf_dir = Self::determine_conf_dir()?;
+            let gtfs = self.get_gtfs_file()?;
+
+            // 2. read origin and destination stops.
+            let wiz = Wizard::new(&conf_dir).await;
+            self.user_connections.push(wiz.read_connection(&gtfs)?);
+
+            // 3. build connections database.
+            self.connections =
+                Self::build_connections_database(&gtfs, &self.user_connections).await?;
+
+            // 4. save config
+            self.save().await?;
+        }
+
+        // -C argument
+        if args.is_present("delete-connection") {
+            // 1. determine the connection.
+            let to_be_removed = Ui::select_connection(
+                "Please enter the number of connection you want to delete:",
+                &self.user_connections,
+            )?;
+
+            // 2. remove the connection (database doesn't need to be rebuilt).
+            let connection = self.user_connections.remove(to_be_removed);
+            self.connections.remove(to_be_removed);
+            let msg = format!("Connection {} has been removed.", &connection);
+            Ui::info(&msg);
+
+            self.save().await?;
+        }
+
         // -w argument
         if args.is_present("wipe") && Ui::confirm("Do you want to wipe whole app config?") {
             self.wipe().await?;
@@ -1,13 +1,12 @@
 use std::path::{Path, PathBuf};
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use std::sync::Arc;
 
-#[cfg(feature = "prague")]
-use crate::features::prague::Additional;
-use chrono::NaiveDate;
-use gtfs_structures::{Gtfs, Stop, Trip};
+use crate::error::Error;
+use crate::realtime::Realtime;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Weekday};
+use gtfs_structures::{
+    Availability, BikesAllowedType, Exception, Gtfs, Route, Stop, StopTime, Trip,
+};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
@@ -23,6 +22,103 @@ pub struct CustomCalendar {
     pub sunday: bool,
     pub start_date: NaiveDate,
     pub end_date: NaiveDate,
+    /// Exceptions (calendar dates) - the service runs on the added dates
+    /// and doesn't run on the removed ones regardless of the week days.
+    #[serde(default)]
+    pub added: Vec<NaiveDate>,
+    #[serde(default)]
+    pub removed: Vec<NaiveDate>,
+}
+
+impl CustomCalendar {
+    /// Calendar of a service running on the single day.
+    pub fn day(date: NaiveDate) -> Self {
+        Self {
+            monday: true,
+            tuesday: true,
+            wednesday: true,
+            thursday: true,
+            friday: true,
+            saturday: true,
+            sunday: true,
+            start_date: date,
+            end_date: date,
+            added: vec![],
+            removed: vec![],
+        }
+    }
+
+    /// Builds calendar of the service with its exceptions. Services without
+    /// a calendar run on the added dates only (calendar dates define them).
+    pub fn service(gtfs: &Gtfs, service_id: &str) -> Result<Self, Error> {
+        let dates = gtfs
+            .calendar_dates
+            .get(service_id)
+            .map_or(&[][..], Vec::as_slice);
+        let (added, removed): (Vec<_>, Vec<_>) = dates
+            .iter()
+            .partition(|d| matches!(d.exception_type, Exception::Added));
+        let added: Vec<NaiveDate> = added.iter().map(|d| d.date).collect();
+        let removed: Vec<NaiveDate> = removed.iter().map(|d| d.date).collect();
+
+        let calendar = match gtfs.calendar.get(service_id) {
+            Some(calendar) => Self::from(calendar),
+            None => {
+                let (Some(start_date), Some(end_date)) = (added.iter().min(), added.iter().max())
+                else {
+                    return Err(
+                        gtfs_structures::Error::ReferenceError(service_id.to_string()).into(),
+                    );
+                };
+
+                Self {
+                    monday: false,
+                    tuesday: false,
+                    wednesday: false,
+                    thursday: false,
+                    friday: false,
+                    saturday: false,
+                    sunday: false,
+                    start_date: *start_date,
+                    end_date: *end_date,
+                    added: vec![],
+                    removed: vec![],
+                }
+            }
+        };
+
+        Ok(Self {
+            added,
+            removed,
+            ..calendar
+        })
+    }
+
+    /// Checks if the service runs on the given date - exceptions take
+    /// precedence over the week days.
+    pub fn runs_on(&self, date: NaiveDate) -> bool {
+        if self.removed.contains(&date) {
+            return false;
+        }
+
+        if self.added.contains(&date) {
+            return true;
+        }
+
+        if date < self.start_date || date > self.end_date {
+            return false;
+        }
+
+        match date.weekday() {
+            Weekday::Mon => self.monday,
+            Weekday::Tue => self.tuesday,
+            Weekday::Wed => self.wednesday,
+            Weekday::Thu => self.thursday,
+            Weekday::Fri => self.friday,
+            Weekday::Sat => self.saturday,
+            Weekday::Sun => self.sunday,
+        }
+    }
 }
 
 impl From<&gtfs_structures::Calendar> for CustomCalendar {
@@ -37,6 +133,8 @@ impl From<&gtfs_structures::Calendar> for CustomCalendar {
             sunday: cal.sunday,
             start_date: cal.start_date,
             end_date: cal.end_date,
+            added: vec![],
+            removed: vec![],
         }
     }
 }
@@ -44,15 +142,72 @@ impl From<&gtfs_structures::Calendar> for CustomCalendar {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Record {
     pub route: String, // human readable line name
-    // pub route_id: String,
+    #[serde(default)]
+    pub route_id: Option<String>,
     pub trip: String,
     pub trip_id: String,
     pub calendar: CustomCalendar,
     pub stop_time: Option<u32>,
     pub stop: String,
-    #[cfg(feature = "prague")]
+    /// Stop ID and position of the stop in the trip (to match realtime
+    /// updates).
+    #[serde(default)]
+    pub stop_id: Option<String>,
+    #[serde(default)]
+    pub stop_sequence: Option<u16>,
+    pub trip_headsign: Option<String>,
+    pub stop_headsign: Option<String>,
+    pub wheelchair_accessible: Option<bool>,
+    pub bikes_allowed: Option<bool>,
+    /// Arrival to the destination stop (connections only).
+    pub destination_time: Option<u32>,
+    /// Route color and route text color as hex (RRGGBB).
+    #[serde(default)]
+    pub route_color: Option<String>,
+    #[serde(default)]
+    pub route_text_color: Option<String>,
+    /// Service day the departure belongs to (filled in by timetables).
     #[serde(skip)]
-    pub additionals: Option<Additional>,
+    pub service_date: Option<NaiveDate>,
+    /// Realtime info (filled in by realtime providers).
+    #[serde(skip)]
+    pub realtime: Option<Realtime>,
+}
+
+impl Record {
+    /// Returns the destination shown to passengers at this stop. Stop headsign
+    /// takes precedence since it overrides the trip headsign for a part of
+    /// the trip.
+    pub fn headsign(&self) -> Option<&str> {
+        self.stop_headsign
+            .as_deref()
+            .or(self.trip_headsign.as_deref())
+    }
+
+    /// Returns date and time of the departure. Stop time is counted from
+    /// the midnight of the service day so it can exceed 24 hours.
+    pub fn departure(&self) -> Option<NaiveDateTime> {
+        let midnight = self.service_date?.and_hms_opt(0, 0, 0)?;
+
+        Some(midnight + Duration::seconds(self.stop_time?.into()))
+    }
+
+    /// Returns the predicted departure (realtime) or the scheduled one when
+    /// there's no prediction.
+    pub fn expected_departure(&self) -> Option<NaiveDateTime> {
+        self.realtime
+            .as_ref()
+            .and_then(|r| r.departure)
+            .or_else(|| self.departure())
+    }
+
+    /// Returns date and time of the arrival to the destination stop
+    /// (connections only).
+    pub fn arrival(&self) -> Option<NaiveDateTime> {
+        let midnight = self.service_date?.and_hms_opt(0, 0, 0)?;
+
+        Some(midnight + Duration::seconds(self.destination_time?.into()))
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -62,7 +217,7 @@ pub struct Database {
 }
 
 impl<'a> Database {
-    pub fn from(gtfs: &'a Gtfs, stop: Arc<Stop>) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn from(gtfs: &'a Gtfs, stop: Arc<Stop>) -> Result<Self, Error> {
         let records = Self::fetch(gtfs, stop)?;
         // Self::debug(routes_and_calendars);
 
@@ -72,42 +227,108 @@ impl<'a> Database {
     /// Walks thru all stops and collects all trips that intersect any
     /// of selected stop.
     /// Uses parallel iterating (rayon)
-    fn fetch(gtfs: &'a Gtfs, stop: Arc<Stop>) -> Result<Vec<Record>, Box<dyn std::error::Error>> {
-        let records = Arc::new(Mutex::new(vec![]));
-
-        gtfs.routes.par_iter().for_each(|(_, route)| {
-            let records = Arc::clone(&records);
-
-            for (_, trip) in gtfs
-                .trips
-                .par_iter()
-                .filter(|trip| trip.1.route_id == route.id)
-                .collect::<HashMap<&String, &Trip>>()
-            {
-                for time in trip.stop_times.iter() {
-                    if time.stop.id == stop.id {
-                        records.lock().unwrap().push(Record {
-                            route: route.short_name.clone(),
-                            // route_id: route.id.clone(),
-                            trip: trip.service_id.clone(),
-                            trip_id: trip.id.clone(),
-                            calendar: CustomCalendar::from(
-                                gtfs.get_calendar(trip.service_id.as_str()).unwrap(),
-                            ),
-                            stop_time: time.arrival_time,
-                            stop: time.stop.name.clone(),
-                            #[cfg(feature = "prague")]
-                            additionals: None,
-                        });
-                    }
-                }
-            }
-        });
+    fn fetch(gtfs: &'a Gtfs, stop: Arc<Stop>) -> Result<Vec<Record>, Error> {
+        gtfs.trips
+            .par_iter()
+            // Trips of unknown routes are skipped.
+            .filter_map(|(_, trip)| Some((trip, gtfs.routes.get(&trip.route_id)?)))
+            .flat_map_iter(|(trip, route)| {
+                trip.stop_times
+                    .iter()
+                    .filter(|time| time.stop.id == stop.id)
+                    .map(move |time| Self::record(gtfs, route, trip, time))
+            })
+            .collect()
+    }
+
+    /// Builds database of direct trips from one stop to another.
+    pub fn from_connection(gtfs: &'a Gtfs, from: Arc<Stop>, to: Arc<Stop>) -> Result<Self, Error> {
+        let records = Self::fetch_connection(gtfs, from, to)?;
+
+        Ok(Self { records })
+    }
+
+    /// Walks thru all trips and collects those which serve both stops
+    /// in the right order. Stop time of the record is the departure
+    /// from the origin stop, destination time is the arrival to the
+    /// destination stop.
+    /// Uses parallel iterating (rayon)
+    fn fetch_connection(
+        gtfs: &'a Gtfs,
+        from: Arc<Stop>,
+        to: Arc<Stop>,
+    ) -> Result<Vec<Record>, Error> {
+        gtfs.trips
+            .par_iter()
+            .filter_map(|(_, trip)| {
+                let departure = trip.stop_times.iter().position(|t| t.stop.id == from.id)?;
+                let arrival = trip.stop_times[departure..]
+                    .iter()
+                    .find(|t| t.stop.id == to.id)?;
+                let departure = &trip.stop_times[departure];
+                let route = gtfs.routes.get(&trip.route_id)?;
 
-        Ok(Mutex::into_inner(Arc::try_unwrap(records).unwrap()).unwrap())
+                Some(
+                    Self::record(gtfs, route, trip, departure).map(|record| Record {
+                        stop_time: departure.departure_time.or(departure.arrival_time),
+                        destination_time: arrival.arrival_time.or(arrival.departure_time),
+                        ..record
+                    }),
+                )
+            })
+            .collect()
+    }
+
+    /// Creates record of the trip stopping at the stop. Fails when
+    /// the service of the trip has neither calendar nor calendar dates.
+    fn record(gtfs: &Gtfs, route: &Route, trip: &Trip, time: &StopTime) -> Result<Record, Error> {
+        Ok(Record {
+            route: route.short_name.clone(),
+            route_id: Some(route.id.clone()),
+            trip: trip.service_id.clone(),
+            trip_id: trip.id.clone(),
+            calendar: CustomCalendar::service(gtfs, &trip.service_id)?,
+            // Departure differs from the arrival when the vehicle waits.
+            stop_time: time.departure_time.or(time.arrival_time),
+            stop: time.stop.name.clone(),
+            stop_id: Some(time.stop.id.clone()),
+            stop_sequence: Some(time.stop_sequence),
+            trip_headsign: trip.trip_headsign.clone(),
+            stop_headsign: time.stop_headsign.clone(),
+            wheelchair_accessible: availability(trip.wheelchair_accessible),
+            bikes_allowed: match trip.bikes_allowed {
+                BikesAllowedType::AtLeastOneBike => Some(true),
+                BikesAllowedType::NoBikesAllowed => Some(false),
+                _ => None,
+            },
+            destination_time: None,
+            route_color: Some(hex_color(route.color.r, route.color.g, route.color.b)),
+            route_text_color: Some(hex_color(
+                route.text_color.r,
+                route.text_color.g,
+                route.text_color.b,
+            )),
+            service_date: None,
+            realtime: None,
+        })
+    }
+}
+
+/// Converts GTFS availability (wheelchair accessibility) to a flag.
+/// None means the information is not available.
+pub fn availability(availability: Availability) -> Option<bool> {
+    match availability {
+        Availability::Available => Some(true),
+        Availability::NotAvailable => Some(false),
+        _ => None,
     }
 }
 
+/// Converts GTFS color to hex (RRGGBB).
+fn hex_color(r: u8, g: u8, b: u8) -> String {
+    format!("{:02X}{:02X}{:02X}", r, g, b)
+}
+
 /// Represents GTFS file wrapper for manipulation like downloading or parsing.
 pub struct DataFile {
     remote_location: String,
@@ -127,8 +348,7 @@ impl DataFile {
     }
 
     /// Downloads or copies the data file into config folder.
-    /// TODO: custom error
-    pub async fn retrieve(&self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    pub async fn retrieve(&self) -> Result<PathBuf, Error> {
         // Download from the internet
         // or copy from existing location.
         if self.remote_location.starts_with("http") {
@@ -145,7 +365,83 @@ impl DataFile {
     }
 
     /// Parses earlier downloaded GTFS file.
-    pub fn parse(&self) -> Result<Gtfs, Box<dyn std::error::Error>> {
-        Ok(Gtfs::new(self.local_location.to_str().unwrap())?)
+    pub fn parse(&self) -> Result<Gtfs, Error> {
+        Ok(Gtfs::new(&self.local_location.to_string_lossy())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{date, gtfs, record};
+
+    #[test]
+    fn calendar_exceptions() {
+        let calendar = CustomCalendar::service(&gtfs("calendar-exceptions"), "weekdays").unwrap();
+
+        assert!(!calendar.runs_on(date(1)));
+        assert!(calendar.runs_on(date(2)));
+        assert!(calendar.runs_on(date(4)));
+        assert!(!calendar.runs_on(date(5)));
+    }
+
+    #[test]
+    fn calendar_dates_only() {
+        let calendar = CustomCalendar::service(&gtfs("calendar-dates-only"), "dates").unwrap();
+
+        assert_eq!(
+            (calendar.start_date, calendar.end_date),
+            (date(3), date(10))
+        );
+        assert!(calendar.runs_on(date(3)));
+        assert!(calendar.runs_on(date(10)));
+        assert!(!calendar.runs_on(date(6)));
+    }
+
+    #[test]
+    fn record_departs_after_dwell() {
+        let gtfs = gtfs("record-departs-after-dwell");
+        let trip = Trip {
+            id: "trip".to_string(),
+            service_id: "weekdays".to_string(),
+            ..Trip::default()
+        };
+        let time = StopTime {
+            stop: Arc::new(Stop::default()),
+            arrival_time: Some(9 * 3600),
+            departure_time: Some(9 * 3600 + 120),
+            ..StopTime::default()
+        };
+
+        let record = Database::record(&gtfs, &Route::default(), &trip, &time).unwrap();
+
+        assert_eq!(record.stop_time, Some(9 * 3600 + 120));
+    }
+
+    #[test]
+    fn record_expected_departure() {
+        let mut record = record();
+        let departure = date(6).and_hms_opt(9, 0, 0).unwrap();
+
+        assert_eq!(record.departure(), Some(departure));
+        assert_eq!(record.expected_departure(), Some(departure));
+
+        record.realtime = Some(Realtime {
+            departure: Some(departure + Duration::minutes(2)),
+            ..Realtime::default()
+        });
+
+        assert_eq!(
+            record.expected_departure(),
+            Some(departure + Duration::minutes(2))
+        );
+    }
+
+    #[test]
+    fn calendar_unknown_service() {
+        assert!(matches!(
+            CustomCalendar::service(&gtfs("calendar-unknown-service"), "unknown"),
+            Err(Error::Gtfs(_))
+        ));
     }
 }
@@ -0,0 +1,79 @@
+use std::{fmt, io};
+
+/// Errors of trs.
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid user input (argument values, unknown stops...).
+    Input(String),
+    /// Config is not available or is invalid.
+    Config(String),
+    /// Reading or writing of a file failed.
+    Io(io::Error),
+    /// Data file couldn't be downloaded.
+    Download(reqwest::Error),
+    /// Data file couldn't be parsed or its data are invalid.
+    Gtfs(gtfs_structures::Error),
+    /// Realtime data couldn't be fetched.
+    Realtime(String),
+    /// System service (D-Bus session bus, network socket of the server)
+    /// couldn't be used.
+    Service(String),
+}
+
+impl Error {
+    /// Returns exit code of the app for the error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Input(_) => 2,
+            Self::Config(_) => 3,
+            Self::Io(_) => 4,
+            Self::Download(_) => 5,
+            Self::Gtfs(_) => 6,
+            Self::Realtime(_) => 7,
+            Self::Service(_) => 8,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Input(msg) => write!(f, "{}", msg),
+            Self::Config(msg) => write!(f, "{}", msg),
+            Self::Io(e) => write!(f, "File operation failed: {}", e),
+            Self::Download(e) => write!(f, "Data file download failed: {}", e),
+            Self::Gtfs(e) => write!(f, "Invalid data file: {}", e),
+            Self::Realtime(msg) => write!(f, "Realtime data are not available: {}", msg),
+            Self::Service(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Download(e) => Some(e),
+            Self::Gtfs(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Download(e)
+    }
+}
+
+impl From<gtfs_structures::Error> for Error {
+    fn from(e: gtfs_structures::Error) -> Self {
+        Self::Gtfs(e)
+    }
+}
@@ -0,0 +1,888 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use prost::{Enumeration, Message};
+
+use crate::db::{CustomCalendar, Record};
+use crate::error::Error;
+use crate::realtime::{
+    self, realtime_error, AffectedEntity, Realtime, RealtimeProvider, ServiceAlert, Severity,
+    TripStatus,
+};
+use crate::stop::Stop;
+use crate::timetables::{ConnectionDeparture, Departure};
+
+/// Provider of GTFS-Realtime TripUpdates and Alerts feeds (URL or path
+/// to local file).
+pub struct GtfsRt {
+    trip_updates: Option<String>,
+    alerts: Option<String>,
+}
+
+impl GtfsRt {
+    pub fn new(trip_updates: Option<String>, alerts: Option<String>) -> Self {
+        Self {
+            trip_updates,
+            alerts,
+        }
+    }
+}
+
+#[async_trait]
+impl RealtimeProvider for GtfsRt {
+    /// Predictions are filled in together with the changes of the schedule
+    /// (see `update_schedule()`).
+    async fn enrich(&self, _records: &mut [&mut Record]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn update_schedule(
+        &self,
+        departures: &mut [Departure<'_>],
+        connections: &mut [ConnectionDeparture<'_>],
+    ) -> Result<(), Error> {
+        if let Some(source) = &self.trip_updates {
+            let feed = fetch_feed(source).await?;
+            apply_feed(departures, connections, &feed, Local::now().naive_local());
+        }
+
+        Ok(())
+    }
+
+    async fn alerts(&self) -> Result<Vec<ServiceAlert>, Error> {
+        match &self.alerts {
+            Some(source) => Ok(active_alerts(
+                &fetch_feed(source).await?,
+                Local::now().naive_local(),
+            )),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+/// GTFS-Realtime feed message (only the parts trs makes use of, see
+/// https://gtfs.org/realtime/reference/). Unknown fields are skipped
+/// while decoding.
+#[derive(Clone, PartialEq, Message)]
+pub struct FeedMessage {
+    #[prost(message, required, tag = "1")]
+    pub header: FeedHeader,
+    #[prost(message, repeated, tag = "2")]
+    pub entity: Vec<FeedEntity>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FeedHeader {
+    #[prost(string, required, tag = "1")]
+    pub gtfs_realtime_version: String,
+    #[prost(uint64, optional, tag = "3")]
+    pub timestamp: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FeedEntity {
+    #[prost(string, required, tag = "1")]
+    pub id: String,
+    #[prost(bool, optional, tag = "2")]
+    pub is_deleted: Option<bool>,
+    #[prost(message, optional, tag = "3")]
+    pub trip_update: Option<TripUpdate>,
+    #[prost(message, optional, tag = "5")]
+    pub alert: Option<Alert>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TripUpdate {
+    #[prost(message, required, tag = "1")]
+    pub trip: TripDescriptor,
+    #[prost(message, repeated, tag = "2")]
+    pub stop_time_update: Vec<StopTimeUpdate>,
+    #[prost(uint64, optional, tag = "4")]
+    pub timestamp: Option<u64>,
+    #[prost(int32, optional, tag = "5")]
+    pub delay: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TripDescriptor {
+    #[prost(string, optional, tag = "1")]
+    pub trip_id: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub start_time: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub start_date: Option<String>,
+    #[prost(enumeration = "TripScheduleRelationship", optional, tag = "4")]
+    pub schedule_relationship: Option<i32>,
+    #[prost(string, optional, tag = "5")]
+    pub route_id: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration)]
+#[repr(i32)]
+pub enum TripScheduleRelationship {
+    Scheduled = 0,
+    Added = 1,
+    Unscheduled = 2,
+    Canceled = 3,
+    Replacement = 5,
+    Duplicated = 6,
+    Deleted = 7,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct StopTimeUpdate {
+    #[prost(uint32, optional, tag = "1")]
+    pub stop_sequence: Option<u32>,
+    #[prost(message, optional, tag = "2")]
+    pub arrival: Option<StopTimeEvent>,
+    #[prost(message, optional, tag = "3")]
+    pub departure: Option<StopTimeEvent>,
+    #[prost(string, optional, tag = "4")]
+    pub stop_id: Option<String>,
+    #[prost(enumeration = "StopScheduleRelationship", optional, tag = "5")]
+    pub schedule_relationship: Option<i32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration)]
+#[repr(i32)]
+pub enum StopScheduleRelationship {
+    Scheduled = 0,
+    Skipped = 1,
+    NoData = 2,
+    Unscheduled = 3,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct StopTimeEvent {
+    #[prost(int32, optional, tag = "1")]
+    pub delay: Option<i32>,
+    #[prost(int64, optional, tag = "2")]
+    pub time: Option<i64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Alert {
+    #[prost(message, repeated, tag = "1")]
+    pub active_period: Vec<TimeRange>,
+    #[prost(message, repeated, tag = "5")]
+    pub informed_entity: Vec<EntitySelector>,
+    #[prost(message, optional, tag = "8")]
+    pub url: Option<TranslatedString>,
+    #[prost(message, optional, tag = "10")]
+    pub header_text: Option<TranslatedString>,
+    #[prost(message, optional, tag = "11")]
+    pub description_text: Option<TranslatedString>,
+    #[prost(enumeration = "SeverityLevel", optional, tag = "14")]
+    pub severity_level: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TimeRange {
+    #[prost(uint64, optional, tag = "1")]
+    pub start: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    pub end: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EntitySelector {
+    #[prost(string, optional, tag = "1")]
+    pub agency_id: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub route_id: Option<String>,
+    #[prost(message, optional, tag = "4")]
+    pub trip: Option<TripDescriptor>,
+    #[prost(string, optional, tag = "5")]
+    pub stop_id: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TranslatedString {
+    #[prost(message, repeated, tag = "1")]
+    pub translation: Vec<Translation>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Translation {
+    #[prost(string, required, tag = "1")]
+    pub text: String,
+    #[prost(string, optional, tag = "2")]
+    pub language: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enumeration)]
+#[repr(i32)]
+pub enum SeverityLevel {
+    UnknownSeverity = 1,
+    Info = 2,
+    Warning = 3,
+    Severe = 4,
+}
+
+/// Applies trip updates of the feed to departures (and connections)
+/// as returned by `Timetables` - trips added to the schedule are inserted
+/// (stops only), cancelled ones are marked, departures from skipped stops
+/// and of deleted trips are removed and the rest gets predictions.
+pub fn apply_feed(
+    departures: &mut [Departure<'_>],
+    connections: &mut [ConnectionDeparture<'_>],
+    feed: &FeedMessage,
+    now: NaiveDateTime,
+) {
+    for departure in departures.iter_mut() {
+        departure
+            .departures
+            .extend(added_records(feed, departure.stop, now));
+        departure.departures.sort_by_key(|r| r.departure());
+    }
+
+    let records = departures
+        .iter_mut()
+        .map(|d| &mut d.departures)
+        .chain(connections.iter_mut().map(|c| &mut c.departures));
+
+    for records in records {
+        apply_trip_updates(records, feed, now);
+    }
+}
+
+/// Fetches and decodes the feed. Sources starting with http(s):// are
+/// downloaded (retried when the server is unavailable), anything else is
+/// read as local file.
+pub async fn fetch_feed(source: &str) -> Result<FeedMessage, Error> {
+    let bytes = if source.starts_with("http://") || source.starts_with("https://") {
+        let client = realtime::client_builder().build().map_err(realtime_error)?;
+
+        realtime::send_with_retry(client.get(source))
+            .await?
+            .bytes()
+            .await
+            .map_err(realtime_error)?
+            .to_vec()
+    } else {
+        tokio::fs::read(source).await.map_err(realtime_error)?
+    };
+
+    FeedMessage::decode(bytes.as_slice()).map_err(realtime_error)
+}
+
+/// Collects alerts of the feed active at the time.
+pub fn active_alerts(feed: &FeedMessage, now: NaiveDateTime) -> Vec<ServiceAlert> {
+    feed.entity
+        .iter()
+        .filter(|entity| !entity.is_deleted.unwrap_or(false))
+        .filter_map(|entity| entity.alert.as_ref())
+        .filter_map(|alert| {
+            // Alerts without active period are active all the time.
+            let (active_from, active_until) = if alert.active_period.is_empty() {
+                (None, None)
+            } else {
+                alert
+                    .active_period
+                    .iter()
+                    .map(|period| (period.start.and_then(time), period.end.and_then(time)))
+                    .find(|(start, end)| {
+                        start.is_none_or(|start| start <= now) && end.is_none_or(|end| now < end)
+                    })?
+            };
+
+            Some(ServiceAlert {
+                header: alert.header_text.as_ref().and_then(text)?,
+                description: alert.description_text.as_ref().and_then(text),
+                url: alert.url.as_ref().and_then(text),
+                severity: match alert.severity_level() {
+                    SeverityLevel::UnknownSeverity => Severity::Unknown,
+                    SeverityLevel::Info => Severity::Info,
+                    SeverityLevel::Warning => Severity::Warning,
+                    SeverityLevel::Severe => Severity::Severe,
+                },
+                active_from,
+                active_until,
+                informed_entity: alert
+                    .informed_entity
+                    .iter()
+                    .map(|selector| AffectedEntity {
+                        route_id: selector.route_id.clone(),
+                        trip_id: selector.trip.as_ref().and_then(|t| t.trip_id.clone()),
+                        stop_id: selector.stop_id.clone(),
+                    })
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the text of the string - the translation without language
+/// (the original) or the first one.
+fn text(string: &TranslatedString) -> Option<String> {
+    string
+        .translation
+        .iter()
+        .find(|t| t.language.is_none())
+        .or(string.translation.first())
+        .map(|t| t.text.clone())
+}
+
+/// Converts POSIX time to the local date & time.
+fn time(timestamp: u64) -> Option<NaiveDateTime> {
+    Some(
+        Local
+            .timestamp_opt(timestamp.try_into().ok()?, 0)
+            .single()?
+            .naive_local(),
+    )
+}
+
+/// Matches trip updates of the feed to the records (by trip ID and
+/// service date) and fills in their realtime info. Records of deleted trips
+/// and skipped stops are removed. Records without any update are left
+/// as scheduled.
+pub fn apply_trip_updates(records: &mut Vec<Record>, feed: &FeedMessage, now: NaiveDateTime) {
+    let mut updates: HashMap<&str, Vec<&TripUpdate>> = HashMap::new();
+
+    for update in feed
+        .entity
+        .iter()
+        .filter(|entity| !entity.is_deleted.unwrap_or(false))
+        .filter_map(|entity| entity.trip_update.as_ref())
+    {
+        if let Some(trip_id) = update.trip.trip_id.as_deref() {
+            updates.entry(trip_id).or_default().push(update);
+        }
+    }
+
+    // Service days of the trips closest to now (for updates without date).
+    let mut current: HashMap<String, NaiveDate> = HashMap::new();
+    let distance = |date: NaiveDate| (date - now.date()).num_days().abs();
+
+    for record in records.iter() {
+        if let Some(date) = record.service_date {
+            current
+                .entry(record.trip_id.clone())
+                .and_modify(|c| {
+                    if distance(date) < distance(*c) {
+                        *c = date;
+                    }
+                })
+                .or_insert(date);
+        }
+    }
+
+    records.retain_mut(|record| {
+        let current = current.get(&record.trip_id).copied();
+        let update = updates.get(record.trip_id.as_str()).and_then(|updates| {
+            updates
+                .iter()
+                .find(|u| runs_on(u, record.service_date, current))
+        });
+
+        record.realtime = match update {
+            Some(update) => match update.trip.schedule_relationship() {
+                TripScheduleRelationship::Deleted => return false,
+                // Predicted already (see `added_records()`).
+                TripScheduleRelationship::Added => return true,
+                TripScheduleRelationship::Canceled => Some(Realtime {
+                    status: TripStatus::Canceled,
+                    ..Realtime::default()
+                }),
+                _ => {
+                    let skipped = stop_update(update, record).is_some_and(|u| {
+                        u.schedule_relationship() == StopScheduleRelationship::Skipped
+                    });
+
+                    if skipped {
+                        return false;
+                    }
+
+                    predict(update, record)
+                }
+            },
+            None => None,
+        };
+
+        true
+    });
+}
+
+/// Builds records of trips added to the schedule (not in the timetable)
+/// departing from the stop after now. Route is looked up among scheduled
+/// trips serving the stop.
+pub fn added_records(feed: &FeedMessage, stop: &Stop, now: NaiveDateTime) -> Vec<Record> {
+    feed.entity
+        .iter()
+        .filter(|entity| !entity.is_deleted.unwrap_or(false))
+        .filter_map(|entity| entity.trip_update.as_ref())
+        .filter(|update| update.trip.schedule_relationship() == TripScheduleRelationship::Added)
+        .filter_map(|update| {
+            let stop_update = update.stop_time_update.iter().find(|u| {
+                u.stop_id.as_deref() == Some(stop.id.as_str())
+                    && u.schedule_relationship() != StopScheduleRelationship::Skipped
+            })?;
+            let departure = time(event(stop_update)?.time?.try_into().ok()?)?;
+
+            if departure < now {
+                return None;
+            }
+
+            let service_date = update
+                .trip
+                .start_date
+                .as_deref()
+                .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+                .unwrap_or(departure.date());
+            let stop_time = (departure - service_date.and_hms_opt(0, 0, 0)?)
+                .num_seconds()
+                .try_into()
+                .ok()?;
+            let route = stop
+                .database
+                .records
+                .iter()
+                .find(|r| r.route_id.is_some() && r.route_id == update.trip.route_id);
+
+            Some(Record {
+                route: match route {
+                    Some(route) => route.route.clone(),
+                    None => update.trip.route_id.clone()?,
+                },
+                route_id: update.trip.route_id.clone(),
+                trip: String::new(),
+                trip_id: update.trip.trip_id.clone()?,
+                calendar: CustomCalendar::day(service_date),
+                stop_time: Some(stop_time),
+                stop: stop.name.clone(),
+                stop_id: Some(stop.id.clone()),
+                stop_sequence: stop_update.stop_sequence.and_then(|s| s.try_into().ok()),
+                trip_headsign: None,
+                stop_headsign: None,
+                wheelchair_accessible: None,
+                bikes_allowed: None,
+                destination_time: None,
+                route_color: route.and_then(|r| r.route_color.clone()),
+                route_text_color: route.and_then(|r| r.route_text_color.clone()),
+                service_date: Some(service_date),
+                realtime: Some(Realtime {
+                    departure: Some(departure),
+                    status: TripStatus::Added,
+                    ..Realtime::default()
+                }),
+            })
+        })
+        .collect()
+}
+
+/// Whether the update belongs to the service day (updates without start
+/// date belong to the current one - closest to now).
+fn runs_on(
+    update: &TripUpdate,
+    service_date: Option<NaiveDate>,
+    current: Option<NaiveDate>,
+) -> bool {
+    match update.trip.start_date.as_deref() {
+        Some(start_date) => NaiveDate::parse_from_str(start_date, "%Y%m%d").ok() == service_date,
+        None => service_date.is_some() && service_date == current,
+    }
+}
+
+/// Predicts departure of the record. Update of the stop itself is used
+/// when present, otherwise the delay of the closest preceding stop
+/// (delays propagate downstream) or of the whole trip.
+fn predict(update: &TripUpdate, record: &Record) -> Option<Realtime> {
+    let scheduled = record.departure()?;
+    let sequence = record.stop_sequence.map(u32::from);
+
+    let exact = stop_update(update, record);
+    let preceding = update
+        .stop_time_update
+        .iter()
+        .rev()
+        .find(|u| matches!((u.stop_sequence, sequence), (Some(u), Some(s)) if u < s));
+
+    let delay = exact
+        .and_then(event)
+        .and_then(|event| match (event.time, event.delay) {
+            (Some(time), _) => {
+                Some(Local.timestamp_opt(time, 0).single()?.naive_local() - scheduled)
+            }
+            (None, delay) => delay.map(|d| Duration::seconds(d.into())),
+        })
+        .or_else(|| {
+            preceding
+                .and_then(event)
+                .and_then(|event| event.delay)
+                .or(update.delay)
+                .map(|d| Duration::seconds(d.into()))
+        })?;
+
+    Some(Realtime {
+        departure: Some(scheduled + delay),
+        delay: Some(delay),
+        ..Realtime::default()
+    })
+}
+
+/// Returns the update of the record's stop (if any). Stops are matched
+/// by sequence when known (trips may visit a stop twice), by ID otherwise.
+fn stop_update<'a>(update: &'a TripUpdate, record: &Record) -> Option<&'a StopTimeUpdate> {
+    let sequence = record.stop_sequence.map(u32::from);
+
+    update
+        .stop_time_update
+        .iter()
+        .find(|u| match (u.stop_sequence, sequence) {
+            (Some(u), Some(s)) => u == s,
+            _ => u.stop_id.is_some() && u.stop_id == record.stop_id,
+        })
+}
+
+/// Returns the departure event of the stop (arrival when there's none).
+fn event(update: &StopTimeUpdate) -> Option<&StopTimeEvent> {
+    update.departure.as_ref().or(update.arrival.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use crate::fixtures::{date, record};
+
+    fn stop_time_update(stop_sequence: u32, event: StopTimeEvent) -> StopTimeUpdate {
+        StopTimeUpdate {
+            stop_sequence: Some(stop_sequence),
+            arrival: None,
+            departure: Some(event),
+            stop_id: None,
+            schedule_relationship: None,
+        }
+    }
+
+    fn delay(seconds: i32) -> StopTimeEvent {
+        StopTimeEvent {
+            delay: Some(seconds),
+            time: None,
+        }
+    }
+
+    fn feed(
+        start_date: Option<&str>,
+        trip_delay: Option<i32>,
+        stop_time_update: Vec<StopTimeUpdate>,
+    ) -> FeedMessage {
+        FeedMessage {
+            header: FeedHeader {
+                gtfs_realtime_version: "2.0".to_string(),
+                timestamp: None,
+            },
+            entity: vec![FeedEntity {
+                id: "1".to_string(),
+                is_deleted: None,
+                trip_update: Some(TripUpdate {
+                    trip: TripDescriptor {
+                        trip_id: Some("trip".to_string()),
+                        start_time: None,
+                        start_date: start_date.map(str::to_string),
+                        schedule_relationship: None,
+                        route_id: None,
+                    },
+                    stop_time_update,
+                    timestamp: None,
+                    delay: trip_delay,
+                }),
+                alert: None,
+            }],
+        }
+    }
+
+    /// Feed updating the whole trip of the record.
+    fn trip_feed(relationship: TripScheduleRelationship) -> FeedMessage {
+        let mut feed = feed(None, None, vec![]);
+        let trip = &mut feed.entity[0].trip_update.as_mut().unwrap().trip;
+        trip.schedule_relationship = Some(relationship as i32);
+
+        feed
+    }
+
+    /// Time of the record's departure.
+    fn now() -> NaiveDateTime {
+        record().departure().unwrap()
+    }
+
+    /// Applies the feed to the record and returns its delay.
+    fn applied_delay(feed: &FeedMessage) -> Option<Duration> {
+        let mut records = vec![record()];
+        apply_trip_updates(&mut records, feed, now());
+
+        records[0].realtime.as_ref()?.delay
+    }
+
+    #[test]
+    fn delay_of_the_stop() {
+        let feed = feed(
+            None,
+            Some(600),
+            vec![
+                stop_time_update(3, delay(60)),
+                stop_time_update(5, delay(120)),
+            ],
+        );
+
+        assert_eq!(applied_delay(&feed), Some(Duration::minutes(2)));
+    }
+
+    #[test]
+    fn delay_from_predicted_time() {
+        let departure = record().departure().unwrap() + Duration::minutes(3);
+        let time = Local.from_local_datetime(&departure).unwrap().timestamp();
+        let event = StopTimeEvent {
+            delay: Some(60),
+            time: Some(time),
+        };
+
+        let mut records = vec![record()];
+        apply_trip_updates(
+            &mut records,
+            &feed(None, None, vec![stop_time_update(5, event)]),
+            now(),
+        );
+        let realtime = records[0].realtime.as_ref().unwrap();
+
+        assert_eq!(realtime.delay, Some(Duration::minutes(3)));
+        assert_eq!(realtime.departure, Some(departure));
+    }
+
+    #[test]
+    fn delay_of_preceding_stop() {
+        let feed = feed(
+            None,
+            Some(600),
+            vec![
+                stop_time_update(2, delay(30)),
+                stop_time_update(4, delay(60)),
+                stop_time_update(7, delay(300)),
+            ],
+        );
+
+        assert_eq!(applied_delay(&feed), Some(Duration::minutes(1)));
+    }
+
+    #[test]
+    fn delay_of_trip() {
+        let feed = feed(None, Some(240), vec![stop_time_update(7, delay(300))]);
+
+        assert_eq!(applied_delay(&feed), Some(Duration::minutes(4)));
+    }
+
+    #[test]
+    fn no_delay() {
+        assert_eq!(applied_delay(&feed(None, None, vec![])), None);
+    }
+
+    #[test]
+    fn update_of_service_day() {
+        let update = |start_date| feed(start_date, Some(120), vec![]);
+
+        assert_eq!(
+            applied_delay(&update(Some("20240506"))),
+            Some(Duration::minutes(2))
+        );
+        assert_eq!(applied_delay(&update(None)), Some(Duration::minutes(2)));
+        assert_eq!(applied_delay(&update(Some("20240505"))), None);
+    }
+
+    #[test]
+    fn undated_update_of_closest_service_day() {
+        let record_of = |day| Record {
+            calendar: CustomCalendar::day(date(day)),
+            service_date: Some(date(day)),
+            ..record()
+        };
+        // Trip of the previous day still running after midnight.
+        let now = date(7).and_hms_opt(0, 30, 0).unwrap();
+
+        let mut records = vec![record_of(6), record_of(7), record_of(8)];
+        apply_trip_updates(&mut records, &feed(None, Some(120), vec![]), now);
+        let delays: Vec<_> = records
+            .iter()
+            .map(|r| r.realtime.as_ref().and_then(|r| r.delay))
+            .collect();
+
+        assert_eq!(delays, [None, Some(Duration::minutes(2)), None]);
+    }
+
+    #[test]
+    fn stop_matched_by_sequence() {
+        // Loop trip visiting the stop as the 2nd and the 5th one.
+        let update = |sequence, seconds| StopTimeUpdate {
+            stop_id: Some("S5".to_string()),
+            ..stop_time_update(sequence, delay(seconds))
+        };
+        let feed = feed(None, None, vec![update(2, 60), update(5, 180)]);
+
+        assert_eq!(applied_delay(&feed), Some(Duration::minutes(3)));
+
+        let mut records = vec![Record {
+            stop_sequence: None,
+            ..record()
+        }];
+        apply_trip_updates(&mut records, &feed, now());
+
+        assert_eq!(
+            records[0].realtime.as_ref().unwrap().delay,
+            Some(Duration::minutes(1))
+        );
+    }
+
+    #[test]
+    fn skipped_stop() {
+        let mut update = stop_time_update(5, delay(0));
+        update.schedule_relationship = Some(StopScheduleRelationship::Skipped as i32);
+
+        let mut records = vec![record()];
+        apply_trip_updates(&mut records, &feed(None, None, vec![update]), now());
+
+        assert!(records.is_empty());
+    }
+
+    #[test]
+    fn canceled_trip() {
+        let mut records = vec![record()];
+        apply_trip_updates(
+            &mut records,
+            &trip_feed(TripScheduleRelationship::Canceled),
+            now(),
+        );
+
+        let realtime = records[0].realtime.as_ref().unwrap();
+        assert_eq!(realtime.status, TripStatus::Canceled);
+        assert_eq!(realtime.departure, None);
+    }
+
+    #[test]
+    fn deleted_trip() {
+        let mut records = vec![record()];
+        apply_trip_updates(
+            &mut records,
+            &trip_feed(TripScheduleRelationship::Deleted),
+            now(),
+        );
+
+        assert!(records.is_empty());
+    }
+
+    #[test]
+    fn added_trip() {
+        let stop = Stop {
+            database: Database {
+                records: vec![record()],
+            },
+            ..crate::fixtures::stop()
+        };
+        let now = record().departure().unwrap();
+        let departure = now + Duration::minutes(5);
+
+        let mut feed = trip_feed(TripScheduleRelationship::Added);
+        let update = feed.entity[0].trip_update.as_mut().unwrap();
+        update.trip.trip_id = Some("extra".to_string());
+        update.trip.route_id = Some("L136".to_string());
+        update.trip.start_date = Some("20240506".to_string());
+        update.stop_time_update = vec![StopTimeUpdate {
+            stop_id: Some("S5".to_string()),
+            ..stop_time_update(
+                5,
+                StopTimeEvent {
+                    delay: None,
+                    time: Some(Local.from_local_datetime(&departure).unwrap().timestamp()),
+                },
+            )
+        }];
+
+        let mut departures = vec![Departure {
+            stop: &stop,
+            departures: vec![record()],
+            alerts: vec![],
+            realtime_unavailable: false,
+        }];
+        apply_feed(&mut departures, &mut [], &feed, now);
+
+        let records = &departures[0].departures;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].trip_id, "trip");
+        assert_eq!(records[1].trip_id, "extra");
+        assert_eq!(records[1].route, "136");
+        assert_eq!(records[1].stop_time, Some(9 * 3600 + 300));
+        assert_eq!(records[1].departure(), Some(departure));
+
+        let realtime = records[1].realtime.as_ref().unwrap();
+        assert_eq!(realtime.status, TripStatus::Added);
+        assert_eq!(realtime.departure, Some(departure));
+    }
+
+    /// Feed with alerts headed by their index, active in the periods
+    /// (`None` bounds are open).
+    fn alerts_feed(
+        periods: Vec<Vec<(Option<NaiveDateTime>, Option<NaiveDateTime>)>>,
+    ) -> FeedMessage {
+        let timestamp =
+            |time: NaiveDateTime| Local.from_local_datetime(&time).unwrap().timestamp() as u64;
+
+        FeedMessage {
+            header: FeedHeader {
+                gtfs_realtime_version: "2.0".to_string(),
+                timestamp: None,
+            },
+            entity: periods
+                .into_iter()
+                .enumerate()
+                .map(|(i, periods)| FeedEntity {
+                    id: i.to_string(),
+                    is_deleted: None,
+                    trip_update: None,
+                    alert: Some(Alert {
+                        active_period: periods
+                            .into_iter()
+                            .map(|(start, end)| TimeRange {
+                                start: start.map(timestamp),
+                                end: end.map(timestamp),
+                            })
+                            .collect(),
+                        header_text: Some(TranslatedString {
+                            translation: vec![Translation {
+                                text: i.to_string(),
+                                language: None,
+                            }],
+                        }),
+                        ..Alert::default()
+                    }),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn alerts_active_now() {
+        let now = record().departure().unwrap();
+        let hour = Duration::hours(1);
+
+        let feed = alerts_feed(vec![
+            // Expired.
+            vec![(Some(now - hour * 2), Some(now - hour))],
+            // Future.
+            vec![(Some(now + hour), None)],
+            // Without active period.
+            vec![],
+            // Second period active.
+            vec![(None, Some(now - hour)), (Some(now), Some(now + hour))],
+            // Ending now.
+            vec![(Some(now - hour), Some(now))],
+        ]);
+
+        let alerts = active_alerts(&feed, now);
+        let headers: Vec<_> = alerts.iter().map(|a| a.header.as_str()).collect();
+
+        assert_eq!(headers, ["2", "3"]);
+        assert_eq!(alerts[0].active_from, None);
+        assert_eq!(alerts[1].active_from, Some(now));
+        assert_eq!(alerts[1].active_until, Some(now + hour));
+    }
+}
@@ -1,2 +1,4 @@
+#[cfg(feature = "gtfs-rt")]
+pub mod gtfs_rt;
 #[cfg(feature = "prague")]
 pub mod prague;
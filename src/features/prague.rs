@@ -1,131 +1,744 @@
-use std::{collections::HashMap, fmt, rc::Rc, sync::Arc, time::Duration, usize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::{fmt, sync::Arc, time::Duration};
 
-use crate::{db::Record, ui::UiConfig};
+use crate::db::Record;
+use crate::error::Error;
+use crate::realtime::{self, realtime_error, Realtime, RealtimeProvider, TripStatus, Vehicle};
+use crate::timetables::{ConnectionDeparture, Departure};
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
 use futures::future::join_all;
-use reqwest::{header, StatusCode};
+use reqwest::header;
 use serde_json;
 use std::env;
 use strfmt::strfmt;
-use tokio::{sync::Semaphore, time::sleep};
+use tokio::sync::{OnceCell, Semaphore};
+use tokio::{fs, process::Command};
 
 const GOLEMIO_API_URL: &str = "https://api.golemio.cz/v2/vehiclepositions?offset=0&includeNotTracking=true&includeNotPublic=false&includePositions=false&preferredTimezone=Europe%2FPrague&routeShortName={route}";
+const GOLEMIO_STOPS_URL: &str = "https://api.golemio.cz/v2/gtfs/stops?{ids}";
+const GOLEMIO_BOARDS_URL: &str = "https://api.golemio.cz/v2/pid/departureboards?ids[]={stop}&minutesBefore=10&minutesAfter=180&limit=100&preferredTimezone=Europe%2FPrague";
 const GOLEMIO_API_RATE_LIMIT: usize = 5;
-const GOLEMIOAPI_COOL_OFF: Duration = Duration::from_millis(2000);
+const GOLEMIO_API_KEY_VAR: &str = "GOLEMIO_API_KEY";
+/// How long are vehicle positions of a route reused from the disk cache.
+const GOLEMIO_CACHE_TTL: Duration = Duration::from_secs(30);
 
+/// Provider of delays and air-condition info of Prague integrated
+/// transport (Golemio API).
 #[cfg(feature = "prague")]
-#[derive(Debug, Clone, Copy)]
-pub struct Additional {
-    is_air_conditioned: Option<bool>,
-    delay: Option<Duration>,
+pub struct Golemio {
+    url: Option<String>,
+    stops_url: Option<String>,
+    departure_boards: bool,
+    boards_url: Option<String>,
+    api_key: ApiKey,
+    ca_cert: Option<PathBuf>,
+    cache_dir: Option<PathBuf>,
+    /// Client with the resolved API key (created on the first request).
+    client: OnceCell<reqwest::Client>,
 }
 
-/// Formats additionals like: ❄ [+2 min]
+/// Source of Golemio API key.
 #[cfg(feature = "prague")]
-impl fmt::Display for Additional {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut output = String::new();
-
-        // Air conditioned?
-        if let Some(ac) = self.is_air_conditioned {
-            if ac {
-                output.push_str(" ❄");
+#[derive(Debug, Clone)]
+pub enum ApiKey {
+    /// The key itself.
+    Value(String),
+    /// File containing the key.
+    File(PathBuf),
+    /// Shell command printing the key (e.g. of a password manager).
+    Command(String),
+    /// `GOLEMIO_API_KEY` environment variable.
+    Env,
+}
+
+#[cfg(feature = "prague")]
+impl ApiKey {
+    /// Reads the key from its source (surrounding whitespace trimmed).
+    async fn resolve(&self) -> Result<String, Error> {
+        let key = match self {
+            Self::Value(key) => key.clone(),
+            Self::File(path) => fs::read_to_string(path).await.map_err(|e| {
+                Error::Config(format!(
+                    "Couldn't read Golemio API key from {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?,
+            Self::Command(command) => {
+                let output = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .output()
+                    .await
+                    .map_err(|e| {
+                        Error::Config(format!("Couldn't run Golemio API key command: {}", e))
+                    })?;
+
+                if !output.status.success() {
+                    return Err(Error::Config(format!(
+                        "Golemio API key command failed ({}).",
+                        output.status
+                    )));
+                }
+
+                String::from_utf8_lossy(&output.stdout).into_owned()
             }
+            Self::Env => env::var(GOLEMIO_API_KEY_VAR).map_err(|_| {
+                Error::Config(format!(
+                    "Golemio API key is not set (api_key in the config file or {} variable).",
+                    GOLEMIO_API_KEY_VAR
+                ))
+            })?,
         };
 
-        // Delayed more than 1 minute?
-        if let Some(d) = self.delay {
-            if d.as_secs() > 60 {
-                output.push_str(format!(" [+{} min]", d.as_secs() / 60).as_str());
+        Ok(key.trim().to_string())
+    }
+}
+
+#[cfg(feature = "prague")]
+impl Golemio {
+    /// Creates provider caching responses in the directory (no caching
+    /// when not given). API key is taken from `GOLEMIO_API_KEY` variable
+    /// unless set otherwise.
+    pub fn new(cache_dir: Option<PathBuf>) -> Self {
+        Self {
+            url: None,
+            stops_url: None,
+            departure_boards: false,
+            boards_url: None,
+            api_key: ApiKey::Env,
+            ca_cert: None,
+            cache_dir,
+            client: OnceCell::new(),
+        }
+    }
+
+    /// Sets vehicle positions URL (with `{route}` placeholder) used instead
+    /// of the Golemio API.
+    pub fn with_url(mut self, url: Option<String>) -> Self {
+        self.url = url;
+
+        self
+    }
+
+    /// Sets stops URL (with `{ids}` placeholder for the query of stop IDs)
+    /// stop names are looked up at instead of the Golemio API.
+    pub fn with_stops_url(mut self, stops_url: Option<String>) -> Self {
+        self.stops_url = stops_url;
+
+        self
+    }
+
+    /// Turns on departure boards of the stops - predictions even of trips
+    /// not started yet, platforms and cancellations. Vehicle positions
+    /// are used when the boards are not available.
+    pub fn with_departure_boards(mut self, departure_boards: bool) -> Self {
+        self.departure_boards = departure_boards;
+
+        self
+    }
+
+    /// Sets departure board URL (with `{stop}` placeholder) used instead
+    /// of the Golemio API.
+    pub fn with_boards_url(mut self, boards_url: Option<String>) -> Self {
+        self.boards_url = boards_url;
+
+        self
+    }
+
+    /// Sets source of the API key.
+    pub fn with_api_key(mut self, api_key: ApiKey) -> Self {
+        self.api_key = api_key;
+
+        self
+    }
+
+    /// Sets PEM file with CA certificate trusted (besides the system ones)
+    /// when verifying the server.
+    pub fn with_ca_cert(mut self, ca_cert: Option<PathBuf>) -> Self {
+        self.ca_cert = ca_cert;
+
+        self
+    }
+
+    /// Returns HTTP client sending the API key. The key is resolved (its
+    /// command run) only once - when the client is created.
+    async fn client(&self) -> Result<reqwest::Client, Error> {
+        self.client
+            .get_or_try_init(|| self.create_client())
+            .await
+            .cloned()
+    }
+
+    /// Creates HTTP client sending the API key.
+    async fn create_client(&self) -> Result<reqwest::Client, Error> {
+        let api_key = self.api_key.resolve().await?;
+
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            "X-Access-Token",
+            header::HeaderValue::from_str(&api_key)
+                .map_err(|_| Error::Config("Invalid Golemio API key.".to_string()))?,
+        );
+        headers.insert(
+            "Accept",
+            header::HeaderValue::from_static("application/json"),
+        );
+
+        let mut builder = realtime::client_builder().default_headers(headers);
+
+        if let Some(path) = &self.ca_cert {
+            let ca_error = |e: &dyn fmt::Display| {
+                Error::Config(format!("Invalid CA certificate {}: {}", path.display(), e))
             };
+            let pem = fs::read(path).await.map_err(|e| ca_error(&e))?;
+            let certificate = reqwest::Certificate::from_pem(&pem).map_err(|e| ca_error(&e))?;
+
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        builder.build().map_err(realtime_error)
+    }
+}
+
+#[cfg(feature = "prague")]
+#[async_trait]
+impl RealtimeProvider for Golemio {
+    async fn enrich(&self, records: &mut [&mut Record]) -> Result<(), Error> {
+        let client = self.client().await?;
+        let url = self.url.as_deref().unwrap_or(GOLEMIO_API_URL);
+        let stops_url = self.stops_url.as_deref().unwrap_or(GOLEMIO_STOPS_URL);
+
+        spice_up_departures(records, client, url, stops_url, self.cache_dir.as_deref()).await
+    }
+
+    async fn update_schedule(
+        &self,
+        departures: &mut [Departure<'_>],
+        connections: &mut [ConnectionDeparture<'_>],
+    ) -> Result<(), Error> {
+        if !self.departure_boards {
+            return Ok(());
+        }
+
+        let client = self.client().await?;
+        let url = self.boards_url.as_deref().unwrap_or(GOLEMIO_BOARDS_URL);
+        let mut records: Vec<&mut Record> = departures
+            .iter_mut()
+            .flat_map(|d| d.departures.iter_mut())
+            .chain(connections.iter_mut().flat_map(|c| c.departures.iter_mut()))
+            .collect();
+
+        // Vehicle positions (see `enrich()`) are still applied when boards
+        // fail, the failure is reported like failures of other providers.
+        apply_departure_boards(&mut records, client, url, self.cache_dir.as_deref()).await
+    }
+}
+
+/// Fetches departure board of each stop of the records once (or takes it
+/// from the cache) and fills in predictions, platforms and cancellations
+/// of the records found on the boards.
+#[cfg(feature = "prague")]
+pub async fn apply_departure_boards(
+    records: &mut [&mut Record],
+    client: reqwest::Client,
+    url: &str,
+    cache_dir: Option<&Path>,
+) -> Result<(), Error> {
+    let client = Arc::new(client);
+    let semaphore = Arc::new(Semaphore::new(GOLEMIO_API_RATE_LIMIT));
+
+    // Records of stops and connections share the boards.
+    let stops: BTreeSet<&str> = records
+        .iter()
+        .filter_map(|r| r.stop_id.as_deref())
+        .collect();
+
+    let boards = join_all(stops.into_iter().map(|stop| {
+        fetch_cached(
+            Arc::clone(&client),
+            semaphore.clone(),
+            url,
+            "stop",
+            stop,
+            cache_dir.map(|dir| cache_file_path(dir, "boards", stop)),
+        )
+    }))
+    .await
+    .into_iter()
+    .collect::<Result<HashMap<String, serde_json::Value>, Error>>()?;
+
+    for record in records.iter_mut() {
+        let board = record.stop_id.as_ref().and_then(|id| boards.get(id));
+
+        if let Some(board) = board {
+            parse_board_departure(record, board);
+        }
+    }
+
+    Ok(())
+}
+
+/// Fills in realtime info of the record from its departure on the board
+/// (matched by trip ID and scheduled departure or arrival - they differ
+/// at stops where the vehicle waits).
+#[cfg(feature = "prague")]
+fn parse_board_departure(record: &mut Record, board: &serde_json::Value) {
+    let time = |value: &serde_json::Value| {
+        value
+            .as_str()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.with_timezone(&Local).naive_local())
+    };
+
+    let matched = board["departures"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|d| d["trip"]["id"] == record.trip_id)
+        .find_map(|d| {
+            ["departure_timestamp", "arrival_timestamp"]
+                .into_iter()
+                .find(|kind| time(&d[*kind]["scheduled"]) == record.departure())
+                .map(|kind| (d, kind))
+        });
+
+    if let Some((departure, kind)) = matched {
+        let trip = &departure["trip"];
+        let predicted = time(&departure[kind]["predicted"]);
+        let delay = match departure["delay"]["is_available"].as_bool() {
+            Some(true) => departure["delay"]["seconds"]
+                .as_i64()
+                .map(chrono::Duration::seconds),
+            _ => None,
         };
 
-        write!(f, "{}", output)
+        record.realtime = Some(Realtime {
+            departure: predicted.filter(|_| delay.is_some()),
+            delay,
+            status: match trip["is_canceled"].as_bool() {
+                Some(true) => TripStatus::Canceled,
+                _ => TripStatus::Scheduled,
+            },
+            air_conditioned: trip["is_air_conditioned"].as_bool(),
+            platform: departure["stop"]["platform_code"]
+                .as_str()
+                .map(str::to_string),
+            vehicle: trip["is_wheelchair_accessible"]
+                .as_bool()
+                .map(|low_floor| Vehicle {
+                    low_floor: Some(low_floor),
+                    ..Vehicle::default()
+                }),
+        });
     }
 }
 
+/// Fetches vehicle positions of each route of the records once (or takes
+/// them from the cache) and fills in realtime info of all the records
+/// (names of the last stops passed by their vehicles included).
 #[cfg(feature = "prague")]
-pub async fn spice_up_departures(_config: Rc<UiConfig>, records: &mut [Record]) {
-    let api_key =
-        env::var("GOLEMIO_API_KEY").expect("Environment variable GOLEMIO_API_KEY is not set.");
-
-    // HTTP client.
-    let mut headers = header::HeaderMap::new();
-    headers.insert(
-        "X-Access-Token",
-        header::HeaderValue::from_str(&api_key).unwrap(),
-    );
-    headers.insert(
-        "Accept",
-        header::HeaderValue::from_static("application/json"),
-    );
-    let client = reqwest::Client::builder()
-        .default_headers(headers)
-        // .proxy(Proxy::https("localhost:8080").unwrap())
-        .danger_accept_invalid_certs(true)
-        .build()
-        .unwrap();
+pub async fn spice_up_departures(
+    records: &mut [&mut Record],
+    client: reqwest::Client,
+    url: &str,
+    stops_url: &str,
+    cache_dir: Option<&Path>,
+) -> Result<(), Error> {
     let client = Arc::new(client);
 
     // Tokio semaphore.
     let semaphore = Arc::new(Semaphore::new(GOLEMIO_API_RATE_LIMIT));
 
+    // Each route only once (however many departures or stops it has).
+    let routes: BTreeSet<&str> = records.iter().map(|r| r.route.as_str()).collect();
+
     // Spawn all at once.
-    join_all(
-        records
-            .iter_mut()
-            .map(|record| fetch_record_details(Arc::clone(&client), semaphore.clone(), record)),
-    )
-    .await;
+    let positions = join_all(routes.into_iter().map(|route| {
+        fetch_cached(
+            Arc::clone(&client),
+            semaphore.clone(),
+            url,
+            "route",
+            route,
+            cache_dir.map(|dir| cache_file_path(dir, "positions", route)),
+        )
+    }))
+    .await
+    .into_iter()
+    .collect::<Result<HashMap<String, serde_json::Value>, Error>>()?;
+
+    for record in records.iter_mut() {
+        if let Some(json) = positions.get(&record.route) {
+            parse_record_details(record, json)?;
+        }
+    }
+
+    // Vehicles report IDs of the stops only.
+    let last_stops: BTreeSet<&str> = records
+        .iter()
+        .filter_map(|r| {
+            r.realtime
+                .as_ref()?
+                .vehicle
+                .as_ref()?
+                .last_stop_id
+                .as_deref()
+        })
+        .collect();
+    let names = stop_names(&client, stops_url, cache_dir, last_stops).await;
+
+    for record in records.iter_mut() {
+        if let Some(vehicle) = record.realtime.as_mut().and_then(|r| r.vehicle.as_mut()) {
+            vehicle.last_stop = vehicle
+                .last_stop_id
+                .as_ref()
+                .and_then(|id| names.get(id))
+                .cloned();
+        }
+    }
+
+    Ok(())
 }
 
+/// Looks up names of the stops - in the cache first, the rest is fetched
+/// (and cached as stops are hardly ever renamed). Names are nice to have
+/// only so failed lookup just leaves them out.
 #[cfg(feature = "prague")]
-async fn fetch_record_details(
+async fn stop_names(
+    client: &reqwest::Client,
+    stops_url: &str,
+    cache_dir: Option<&Path>,
+    ids: BTreeSet<&str>,
+) -> HashMap<String, String> {
+    let cache_path = cache_dir.map(|dir| dir.join("golemio").join("stop-names.json"));
+    let mut names: HashMap<String, String> = match &cache_path {
+        Some(path) => fs::read(path)
+            .await
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default(),
+        None => HashMap::new(),
+    };
+
+    let missing: Vec<&str> = ids
+        .into_iter()
+        .filter(|id| !names.contains_key(*id))
+        .collect();
+
+    if missing.is_empty() {
+        return names;
+    }
+
+    let mut map: HashMap<String, String> = HashMap::new();
+    map.insert(
+        "ids".to_string(),
+        form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(missing.iter().map(|id| ("ids[]", id)))
+            .finish(),
+    );
+
+    let Ok(url) = strfmt(stops_url, &map) else {
+        return names;
+    };
+    let Ok(response) = realtime::send_with_retry(client.get(&url)).await else {
+        return names;
+    };
+    let Ok(json) = response.json::<serde_json::Value>().await else {
+        return names;
+    };
+
+    for feature in json["features"].as_array().into_iter().flatten() {
+        let properties = &feature["properties"];
+
+        if let (Some(id), Some(name)) = (
+            properties["stop_id"].as_str(),
+            properties["stop_name"].as_str(),
+        ) {
+            names.insert(id.to_string(), name.to_string());
+        }
+    }
+
+    if let Some(path) = cache_path {
+        if let Ok(json) = serde_json::to_value(&names) {
+            let _ = write_cache(&path, &json).await;
+        }
+    }
+
+    names
+}
+
+/// Returns response of the URL with the placeholder filled in by the key
+/// (route or stop) - fresh enough cached one or fetched (and cached) one.
+#[cfg(feature = "prague")]
+async fn fetch_cached(
     client: Arc<reqwest::Client>,
     semaphore: Arc<Semaphore>,
-    record: &mut Record,
-) {
+    url: &str,
+    placeholder: &str,
+    key: &str,
+    cache_path: Option<PathBuf>,
+) -> Result<(String, serde_json::Value), Error> {
+    if let Some(json) = read_cache(cache_path.as_deref()).await {
+        return Ok((key.to_string(), json));
+    }
+
     // Give signal to semaphore.
-    let _permit = semaphore.acquire().await.unwrap();
+    let _permit = semaphore.acquire().await.map_err(realtime_error)?;
 
-    // Fill in (to URL) the route name.
+    // Fill in (to URL) the route name or stop ID.
     let mut map: HashMap<String, String> = HashMap::new();
-    map.insert("route".to_string(), record.route.clone());
+    map.insert(placeholder.to_string(), key.to_string());
 
-    // Try until (with cool off) HTTP 200 is returned.
-    loop {
-        let result = client
-            .get(strfmt(GOLEMIO_API_URL, &map).unwrap())
-            .send()
-            .await
-            .unwrap();
+    let url = strfmt(url, &map).map_err(realtime_error)?;
 
-        if let StatusCode::OK = result.status() {
-            let json = result.json::<serde_json::Value>().await.unwrap();
-            parse_record_details(record, json);
+    let json = realtime::send_with_retry(client.get(&url))
+        .await?
+        .json::<serde_json::Value>()
+        .await
+        .map_err(realtime_error)?;
 
-            break;
-        }
+    // Failing cache only means fetching again next time.
+    if let Some(path) = cache_path {
+        let _ = write_cache(&path, &json).await;
+    }
+
+    Ok((key.to_string(), json))
+}
+
+/// Cache file of the response - one JSON file per route (or stop) of each
+/// kind of responses (with characters unsafe for file names replaced).
+#[cfg(feature = "prague")]
+fn cache_file_path(cache_dir: &Path, kind: &str, key: &str) -> PathBuf {
+    let name: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    cache_dir
+        .join("golemio")
+        .join(kind)
+        .join(format!("{}.json", name))
+}
+
+/// Reads cached response unless it's missing, older than TTL or broken.
+#[cfg(feature = "prague")]
+async fn read_cache(path: Option<&Path>) -> Option<serde_json::Value> {
+    let path = path?;
+    let age = fs::metadata(path)
+        .await
+        .ok()?
+        .modified()
+        .ok()?
+        .elapsed()
+        .ok()?;
+
+    if age > GOLEMIO_CACHE_TTL {
+        return None;
+    }
 
-        sleep(GOLEMIOAPI_COOL_OFF).await;
+    serde_json::from_slice(&fs::read(path).await.ok()?).ok()
+}
+
+#[cfg(feature = "prague")]
+async fn write_cache(path: &Path, json: &serde_json::Value) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
     }
+    fs::write(path, serde_json::to_vec(json).map_err(realtime_error)?).await?;
+
+    Ok(())
 }
 
 #[cfg(feature = "prague")]
-fn parse_record_details(record: &mut Record, json: serde_json::Value) {
+fn parse_record_details(record: &mut Record, json: &serde_json::Value) -> Result<(), Error> {
+    let features = json["features"]
+        .as_array()
+        .ok_or_else(|| Error::Realtime("Unexpected vehicle positions response.".to_string()))?;
+
     // Find suitable trip_id
-    for feature in json["features"].as_array().unwrap() {
+    for feature in features {
         if feature["properties"]["trip"]["gtfs"]["trip_id"] == record.trip_id {
+            let trip = &feature["properties"]["trip"];
+            let last_position = &feature["properties"]["last_position"];
+
             // Dig out A/C and delay info.
-            let delay = feature["properties"]["last_position"]["delay"]["actual"].clone();
-            let ac = feature["properties"]["trip"]["air_conditioned"].clone();
+            let delay = last_position["delay"]["actual"].clone();
+            let ac = trip["air_conditioned"].clone();
+
+            // Vehicle and the last stop it passed.
+            let last_stop = &last_position["last_stop"];
+            let vehicle = Vehicle {
+                vehicle_type: trip["vehicle_type"]["description_en"]
+                    .as_str()
+                    .map(str::to_lowercase),
+                registration_number: match &trip["vehicle_registration_number"] {
+                    serde_json::Value::String(number) => Some(number.clone()),
+                    serde_json::Value::Number(number) => Some(number.to_string()),
+                    _ => None,
+                },
+                low_floor: trip["wheelchair_accessible"].as_bool(),
+                last_stop_id: last_stop["id"].as_str().map(str::to_string),
+                last_stop: None,
+                stops_away: match (record.stop_sequence, last_stop["sequence"].as_u64()) {
+                    (Some(stop), Some(last)) => u64::from(stop)
+                        .checked_sub(last)
+                        .and_then(|n| u16::try_from(n).ok()),
+                    _ => None,
+                },
+            };
 
-            // Create additional info for each
-            record.additionals = Some(Additional {
-                delay: delay.as_u64().map(Duration::from_secs),
-                is_air_conditioned: ac.as_bool(),
+            // Create realtime info for each (predictions of departure
+            // boards are more accurate so they are kept).
+            let delay = delay.as_i64().map(chrono::Duration::seconds);
+            let departure = record.departure().zip(delay).map(|(d, delay)| d + delay);
+            let realtime = record.realtime.get_or_insert_with(Realtime::default);
+
+            if realtime.departure.is_none() {
+                realtime.departure = departure;
+                realtime.delay = delay;
+            }
+            realtime.air_conditioned = realtime.air_conditioned.or(ac.as_bool());
+            let low_floor = realtime.vehicle.as_ref().and_then(|v| v.low_floor);
+            realtime.vehicle = Some(Vehicle {
+                low_floor: vehicle.low_floor.or(low_floor),
+                ..vehicle
             });
 
             break;
         }
     }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDateTime, TimeZone};
+    use serde_json::json;
+
+    use super::*;
+    use crate::fixtures;
+
+    /// Record of the trip arriving at 9:00 and waiting at the stop
+    /// till 9:02.
+    fn record() -> Record {
+        Record {
+            trip_id: "136_1_1".to_string(),
+            ..fixtures::record()
+        }
+    }
+
+    fn timestamp(time: NaiveDateTime) -> String {
+        Local.from_local_datetime(&time).unwrap().to_rfc3339()
+    }
+
+    /// Board with the trip arriving at the time and departing 2 minutes
+    /// later, both delayed by 3 minutes.
+    fn board(trip_id: &str, arrival: NaiveDateTime) -> serde_json::Value {
+        let departure = arrival + chrono::Duration::minutes(2);
+
+        json!({
+            "departures": [{
+                "arrival_timestamp": {
+                    "scheduled": timestamp(arrival),
+                    "predicted": timestamp(arrival + chrono::Duration::minutes(3)),
+                },
+                "departure_timestamp": {
+                    "scheduled": timestamp(departure),
+                    "predicted": timestamp(departure + chrono::Duration::minutes(3)),
+                },
+                "delay": { "is_available": true, "seconds": 180 },
+                "stop": { "platform_code": "B" },
+                "trip": { "id": trip_id, "is_canceled": false },
+            }]
+        })
+    }
+
+    #[tokio::test]
+    async fn failing_boards_are_reported() {
+        let (url, hits) = fixtures::mock_server(&["404 Not Found"]).await;
+        let golemio = Golemio::new(None)
+            .with_api_key(ApiKey::Value("key".to_string()))
+            .with_departure_boards(true)
+            .with_boards_url(Some(format!("{}{{stop}}", url)));
+        let stop = fixtures::stop();
+        let mut departures = vec![Departure {
+            stop: &stop,
+            departures: vec![record()],
+            alerts: vec![],
+            realtime_unavailable: false,
+        }];
+
+        let outcome = golemio.update_schedule(&mut departures, &mut []).await;
+
+        assert!(matches!(outcome, Err(Error::Realtime(_))));
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn board_departure_matches_arrival_time() {
+        let mut record = record();
+        let arrival = record.departure().unwrap();
+
+        parse_board_departure(&mut record, &board("136_1_1", arrival));
+
+        let realtime = record.realtime.unwrap();
+        assert_eq!(realtime.delay, Some(chrono::Duration::minutes(3)));
+        assert_eq!(
+            realtime.departure,
+            Some(arrival + chrono::Duration::minutes(3))
+        );
+        assert_eq!(realtime.platform.as_deref(), Some("B"));
+    }
+
+    #[test]
+    fn board_departure_matches_departure_time() {
+        let mut record = Record {
+            stop_time: Some(9 * 3600 + 120),
+            ..record()
+        };
+        let departure = record.departure().unwrap();
+        let arrival = departure - chrono::Duration::minutes(2);
+
+        parse_board_departure(&mut record, &board("136_1_1", arrival));
+
+        assert_eq!(
+            record.realtime.unwrap().departure,
+            Some(departure + chrono::Duration::minutes(3))
+        );
+    }
+
+    #[test]
+    fn board_departure_not_matching() {
+        let mut record = record();
+        let arrival = record.departure().unwrap();
+
+        parse_board_departure(&mut record, &board("136_2_1", arrival));
+        parse_board_departure(
+            &mut record,
+            &board("136_1_1", arrival + chrono::Duration::minutes(1)),
+        );
+
+        assert!(record.realtime.is_none());
+    }
+
+    #[tokio::test]
+    async fn api_key_resolved_once() {
+        let log = env::temp_dir().join(format!("trs-api-key-{}", std::process::id()));
+        let golemio = Golemio::new(None).with_api_key(ApiKey::Command(format!(
+            "echo run >> '{}'; echo key",
+            log.display()
+        )));
+
+        golemio.client().await.unwrap();
+        golemio.client().await.unwrap();
+
+        let runs = std::fs::read_to_string(&log).unwrap();
+        std::fs::remove_file(&log).unwrap();
+
+        assert_eq!(runs.lines().count(), 1);
+    }
 }
@@ -0,0 +1,156 @@
+//! Data shared by unit tests of the modules.
+
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+use gtfs_structures::Gtfs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::db::{CustomCalendar, Database, Record};
+use crate::stop::Stop;
+
+/// Returns the day of May 2024 (6th is Monday).
+pub fn date(day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, 5, day).unwrap()
+}
+
+/// Small feed written to a temporary directory (named after the test):
+/// - trip `t` from Alpha (9:10) to Gamma (9:30) waiting at Alpha since 9:08,
+///   Beta is within walking distance of Alpha,
+/// - service `weekdays` runs Mon-Fri in May 2024 except the 1st (holiday)
+///   and on Saturday 4th,
+/// - service `dates` runs on the 3rd and the 10th only (calendar dates).
+pub fn gtfs(test: &str) -> Gtfs {
+    let dir = std::env::temp_dir().join(format!("trs-{}-{}", test, std::process::id()));
+    let files = [
+        (
+            "agency.txt",
+            "agency_id,agency_name,agency_url,agency_timezone\n\
+             A,Agency,https://example.com,Europe/Prague\n",
+        ),
+        (
+            "stops.txt",
+            "stop_id,stop_name,stop_lat,stop_lon\n\
+             a,Alpha,50.0,14.0\n\
+             b,Beta,50.001,14.0\n\
+             g,Gamma,50.1,14.0\n",
+        ),
+        (
+            "routes.txt",
+            "route_id,agency_id,route_short_name,route_long_name,route_type\n\
+             r,A,1,One,3\n",
+        ),
+        (
+            "trips.txt",
+            "route_id,service_id,trip_id\n\
+             r,weekdays,t\n",
+        ),
+        (
+            "stop_times.txt",
+            "trip_id,arrival_time,departure_time,stop_id,stop_sequence\n\
+             t,09:08:00,09:10:00,a,1\n\
+             t,09:30:00,09:30:00,g,2\n",
+        ),
+        (
+            "calendar.txt",
+            "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date\n\
+             weekdays,1,1,1,1,1,0,0,20240501,20240531\n",
+        ),
+        (
+            "calendar_dates.txt",
+            "service_id,date,exception_type\n\
+             weekdays,20240501,2\n\
+             weekdays,20240504,1\n\
+             dates,20240510,1\n\
+             dates,20240503,1\n",
+        ),
+    ];
+
+    fs::create_dir_all(&dir).unwrap();
+
+    for (name, content) in files {
+        fs::write(dir.join(name), content).unwrap();
+    }
+
+    let gtfs = Gtfs::from_path(dir.to_str().unwrap()).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    gtfs
+}
+
+/// Record of trip `trip` (route 136 to Palmovka) departing at 9:00
+/// on Monday 6th from stop S5 (the 5th stop of the trip).
+pub fn record() -> Record {
+    Record {
+        route: "136".to_string(),
+        route_id: Some("L136".to_string()),
+        trip: "service".to_string(),
+        trip_id: "trip".to_string(),
+        calendar: CustomCalendar::day(date(6)),
+        stop_time: Some(9 * 3600),
+        stop: "Skloněná".to_string(),
+        stop_id: Some("S5".to_string()),
+        stop_sequence: Some(5),
+        trip_headsign: Some("Palmovka".to_string()),
+        stop_headsign: None,
+        wheelchair_accessible: None,
+        bikes_allowed: None,
+        destination_time: None,
+        route_color: None,
+        route_text_color: None,
+        service_date: Some(date(6)),
+        realtime: None,
+    }
+}
+
+/// Stop S5 (Skloněná) without any records.
+pub fn stop() -> Stop {
+    Stop {
+        id: "S5".to_string(),
+        name: "Skloněná".to_string(),
+        terminating_stop: "Palmovka".to_string(),
+        wheelchair_boarding: None,
+        database: Database { records: vec![] },
+    }
+}
+
+/// Starts HTTP server answering with the responses (status line and
+/// headers) in order, the last one repeatedly. Returns its URL and
+/// the number of requests received.
+pub async fn mock_server(responses: &[&'static str]) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let hits = Arc::new(AtomicUsize::new(0));
+    let responses = responses.to_vec();
+    let counter = hits.clone();
+
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let hit = counter.fetch_add(1, Ordering::SeqCst);
+            let response = responses[hit.min(responses.len() - 1)];
+
+            // Read the request head (no body is sent).
+            let mut request = vec![];
+            let mut buf = [0; 1024];
+
+            while !request.ends_with(b"\r\n\r\n") {
+                match socket.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                response
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        }
+    });
+
+    (url, hits)
+}
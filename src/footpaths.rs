@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use gtfs_structures::Stop;
+use serde::{Deserialize, Serialize};
+
+/// Mean Earth radius in meters.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+/// Length of one degree of latitude in meters.
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// Walking settings for footpaths between nearby stops.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Walking {
+    /// Maximum walking distance between stops in meters.
+    pub radius: f64,
+    /// Walking speed in km/h.
+    pub speed: f64,
+}
+
+impl Default for Walking {
+    fn default() -> Self {
+        Self {
+            radius: 400.0,
+            speed: 4.5,
+        }
+    }
+}
+
+impl Walking {
+    /// Returns walking duration (seconds) for the given distance (meters).
+    pub fn duration(&self, distance: f64) -> u32 {
+        (distance / (self.speed / 3.6)).ceil() as u32
+    }
+
+    /// Returns distance (meters) walkable in the given duration (seconds).
+    pub fn distance(&self, duration: u32) -> f64 {
+        f64::from(duration) * self.speed / 3.6
+    }
+}
+
+/// Returns great-circle distance between two coordinates in meters.
+pub fn distance(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+/// Parses coordinates given as "LAT,LON".
+pub fn parse_coordinates(value: &str) -> Option<(f64, f64)> {
+    let (lat, lon) = value.split_once(',')?;
+
+    Some((lat.trim().parse().ok()?, lon.trim().parse().ok()?))
+}
+
+/// Returns coordinates (latitude, longitude) of the stop.
+pub fn coordinates(stop: &Stop) -> Option<(f64, f64)> {
+    Some((stop.latitude?, stop.longitude?))
+}
+
+/// Generates footpaths between all stops within the walking radius. Returns
+/// reachable stops (index and walking duration) for each stop.
+/// Stops are put into a grid of radius sized cells so only neighbouring
+/// cells are searched.
+pub fn generate(stops: &[&Stop], walking: &Walking) -> Vec<Vec<(usize, u32)>> {
+    let mut footpaths = vec![vec![]; stops.len()];
+    let cell = walking.radius / METERS_PER_DEGREE;
+
+    if cell <= 0.0 {
+        return footpaths;
+    }
+
+    // Degrees of longitude are shorter away from the equator. All stops
+    // share the scale of the latitude farthest from the equator so
+    // the cells are at least radius wide everywhere (scaling each stop
+    // by its own latitude could put near stops two cells apart).
+    let latitude = stops
+        .iter()
+        .filter_map(|stop| coordinates(stop))
+        .map(|(lat, _)| lat.abs())
+        .fold(0.0, f64::max);
+    let scale = latitude.to_radians().cos().max(f64::EPSILON);
+
+    let cell_of = |(lat, lon): (f64, f64)| {
+        (
+            (lat / cell).floor() as i64,
+            (lon * scale / cell).floor() as i64,
+        )
+    };
+
+    let mut grid: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+
+    for (i, stop) in stops.iter().enumerate() {
+        if let Some(position) = coordinates(stop) {
+            grid.entry(cell_of(position)).or_default().push(i);
+        }
+    }
+
+    for (i, stop) in stops.iter().enumerate() {
+        let position = match coordinates(stop) {
+            Some(position) => position,
+            None => continue,
+        };
+        let (row, column) = cell_of(position);
+
+        for neighbour in (row - 1..=row + 1)
+            .flat_map(|r| (column - 1..=column + 1).map(move |c| (r, c)))
+            .filter_map(|key| grid.get(&key))
+            .flatten()
+        {
+            if *neighbour == i {
+                continue;
+            }
+
+            if let Some(other) = coordinates(stops[*neighbour]) {
+                let meters = distance(position, other);
+
+                if meters <= walking.radius {
+                    footpaths[i].push((*neighbour, walking.duration(meters)));
+                }
+            }
+        }
+    }
+
+    footpaths
+}
+
+/// Finds stops reachable within the walking duration (seconds) from
+/// the given place. Stops are sorted by walking duration.
+pub fn nearby<'a>(
+    stops: impl Iterator<Item = &'a Stop>,
+    place: (f64, f64),
+    walking: &Walking,
+    duration: u32,
+) -> Vec<(&'a Stop, u32)> {
+    let radius = walking.distance(duration);
+
+    let mut found = stops
+        .filter_map(|stop| {
+            let meters = distance(place, coordinates(stop)?);
+
+            if meters <= radius {
+                Some((stop, walking.duration(meters)))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<(&Stop, u32)>>();
+
+    found.sort_by_key(|(_, duration)| *duration);
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(lat: f64, lon: f64) -> Stop {
+        Stop {
+            latitude: Some(lat),
+            longitude: Some(lon),
+            ..Stop::default()
+        }
+    }
+
+    /// Pairs of stops about 334 m apart (north-south) spread over the area
+    /// - every pair has to be connected.
+    fn assert_pairs_connected(lat: f64, lon: f64) {
+        let stops: Vec<Stop> = (0..500)
+            .flat_map(|i| {
+                let (lat, lon) = (lat + 0.0137 * f64::from(i), lon + 0.0071 * f64::from(i));
+
+                [stop(lat, lon), stop(lat + 0.003, lon)]
+            })
+            .collect();
+        let stops: Vec<&Stop> = stops.iter().collect();
+
+        let footpaths = generate(&stops, &Walking::default());
+
+        for pair in 0..500 {
+            assert!(
+                footpaths[2 * pair]
+                    .iter()
+                    .any(|(to, _)| *to == 2 * pair + 1),
+                "pair {} not connected",
+                pair
+            );
+        }
+    }
+
+    #[test]
+    fn footpaths_san_francisco() {
+        assert_pairs_connected(37.5, -122.5);
+    }
+
+    #[test]
+    fn footpaths_sydney() {
+        assert_pairs_connected(-33.9, 151.2);
+    }
+
+    #[test]
+    fn footpaths_within_radius_only() {
+        let stops = [stop(50.0, 14.0), stop(50.003, 14.0), stop(50.01, 14.0)];
+        let stops: Vec<&Stop> = stops.iter().collect();
+
+        let footpaths = generate(&stops, &Walking::default());
+
+        assert_eq!(
+            footpaths[0].iter().map(|(to, _)| *to).collect::<Vec<_>>(),
+            [1]
+        );
+        assert!(footpaths[2].is_empty());
+    }
+}
@@ -0,0 +1,256 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Utc, Weekday};
+
+use crate::db::Record;
+use crate::stop::Stop;
+
+/// Maximum line length (octets) - longer lines are folded.
+const LINE_LENGTH: usize = 75;
+
+/// Builds iCalendar with one event for each departure. Records have to
+/// have the service date set (see `Timetables::get_day_timetable()`).
+pub fn departures_calendar(stop: &Stop, records: &[Record]) -> String {
+    let events = records.iter().filter_map(|record| {
+        let departure = record.departure()?;
+
+        Some(event(
+            &format!("{}-{}", record.trip_id, departure.format("%Y%m%d")),
+            stop,
+            record,
+            departure,
+            vec![],
+        ))
+    });
+
+    calendar(events)
+}
+
+/// Builds iCalendar with one weekly recurring event for each departure
+/// of the timetable. Recurrence is derived from the week days and the date
+/// range of the service limited to the given dates. Exceptions of the
+/// service are listed as excluded (holidays) and extra dates.
+pub fn recurring_calendar(
+    stop: &Stop,
+    records: &[Record],
+    from: NaiveDate,
+    until: NaiveDate,
+) -> String {
+    let events = records.iter().filter_map(|record| {
+        let calendar = &record.calendar;
+        let stop_time = record.stop_time?;
+        let departure_on = |date: NaiveDate| {
+            Record {
+                service_date: Some(date),
+                ..record.clone()
+            }
+            .departure()
+        };
+        let dates = (0..)
+            .map(|i| from + Duration::days(i))
+            .take_while(|d| *d <= until);
+
+        // First service day of the range.
+        let first = dates.clone().find(|d| calendar.runs_on(*d))?;
+        let departure = departure_on(first)?;
+
+        let weekdays = [
+            (calendar.monday, Weekday::Mon),
+            (calendar.tuesday, Weekday::Tue),
+            (calendar.wednesday, Weekday::Wed),
+            (calendar.thursday, Weekday::Thu),
+            (calendar.friday, Weekday::Fri),
+            (calendar.saturday, Weekday::Sat),
+            (calendar.sunday, Weekday::Sun),
+        ];
+        let rule_until = until.min(calendar.end_date);
+        // Whether the weekly rule recurs on the date (after the first day).
+        let recurs = |date: &NaiveDate| {
+            *date <= rule_until
+                && weekdays
+                    .iter()
+                    .any(|(runs, day)| *runs && *day == date.weekday())
+        };
+
+        // Stop times over 24:00:00 depart on the following day(s).
+        let shift = stop_time / 86400;
+        let days = weekdays
+            .iter()
+            .filter(|(runs, _)| *runs)
+            .map(|(_, day)| by_day((0..shift).fold(*day, |day, _| day.succ())))
+            .collect::<Vec<&str>>()
+            .join(",");
+
+        let mut recurrence = vec![];
+
+        if !days.is_empty() && first <= rule_until {
+            recurrence.push(format!(
+                "RRULE:FREQ=WEEKLY;BYDAY={};UNTIL={}T235959",
+                days,
+                (rule_until + Duration::days(shift.into())).format("%Y%m%d")
+            ));
+        }
+
+        // Service days the rule misses (extra dates) and the days it recurs
+        // on although the service doesn't run (holidays).
+        let (extra, excluded): (Vec<NaiveDate>, Vec<NaiveDate>) = dates
+            .filter(|d| *d > first && calendar.runs_on(*d) != recurs(d))
+            .partition(|d| calendar.runs_on(*d));
+
+        for (property, dates) in [("RDATE", extra), ("EXDATE", excluded)] {
+            let times = dates
+                .into_iter()
+                .filter_map(departure_on)
+                .map(|d| d.format("%Y%m%dT%H%M%S").to_string())
+                .collect::<Vec<String>>();
+
+            if !times.is_empty() {
+                recurrence.push(format!("{}:{}", property, times.join(",")));
+            }
+        }
+
+        Some(event(
+            &format!("{}-{}", record.trip_id, stop_time),
+            stop,
+            record,
+            departure,
+            recurrence,
+        ))
+    });
+
+    calendar(events)
+}
+
+/// Wraps events into the calendar.
+fn calendar(events: impl Iterator<Item = Vec<String>>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//trs//departures//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    lines.extend(events.flatten());
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|line| fold(line))
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+/// Builds event of the departure (with recurrence rule and dates if any).
+/// Times are floating (local time of the user).
+fn event(
+    uid: &str,
+    stop: &Stop,
+    record: &Record,
+    departure: NaiveDateTime,
+    recurrence: Vec<String>,
+) -> Vec<String> {
+    let summary = match record.headsign() {
+        Some(headsign) => format!("{} → {}", record.route, headsign),
+        None => record.route.clone(),
+    };
+
+    let mut lines = vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}-{}@trs", escape(uid), escape(&stop.id)),
+        format!("DTSTAMP:{}", Utc::now().format("%Y%m%dT%H%M%SZ")),
+        format!("DTSTART:{}", departure.format("%Y%m%dT%H%M%S")),
+        format!("SUMMARY:{}", escape(&summary)),
+        format!("LOCATION:{}", escape(&stop.name)),
+        format!("DESCRIPTION:Trip {}", escape(&record.trip_id)),
+    ];
+
+    lines.extend(recurrence);
+
+    lines.push("END:VEVENT".to_string());
+
+    lines
+}
+
+/// Returns iCalendar week day abbreviation.
+fn by_day(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+/// Escapes text value.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Folds the content line into lines of max 75 octets (not splitting UTF-8
+/// characters) and terminates it with CRLF.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+
+    for c in line.chars() {
+        if length + c.len_utf8() > LINE_LENGTH {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+
+        folded.push(c);
+        length += c.len_utf8();
+    }
+
+    folded.push_str("\r\n");
+
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::CustomCalendar;
+    use crate::fixtures::{date, gtfs, record, stop};
+
+    /// Event lines of the recurring calendar of the record running
+    /// by the service.
+    fn recurring_event(test: &str, service_id: &str, removed: &[NaiveDate]) -> Vec<String> {
+        let mut calendar = CustomCalendar::service(&gtfs(test), service_id).unwrap();
+        calendar.removed.extend_from_slice(removed);
+        let record = Record {
+            calendar,
+            ..record()
+        };
+
+        recurring_calendar(&stop(), &[record], date(1), date(12))
+            .split("\r\n")
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn recurring_with_exceptions() {
+        let lines = recurring_event("ical-exceptions", "weekdays", &[date(8)]);
+
+        assert!(lines.contains(&"DTSTART:20240502T090000".to_string()));
+        assert!(lines
+            .contains(&"RRULE:FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR;UNTIL=20240512T235959".to_string()));
+        assert!(lines.contains(&"RDATE:20240504T090000".to_string()));
+        assert!(lines.contains(&"EXDATE:20240508T090000".to_string()));
+    }
+
+    #[test]
+    fn recurring_on_dates_only() {
+        let lines = recurring_event("ical-dates-only", "dates", &[]);
+
+        assert!(lines.contains(&"DTSTART:20240503T090000".to_string()));
+        assert!(lines.contains(&"RDATE:20240510T090000".to_string()));
+        assert!(!lines
+            .iter()
+            .any(|l| l.starts_with("RRULE") || l.starts_with("EXDATE")));
+    }
+}
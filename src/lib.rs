@@ -0,0 +1,49 @@
+//! Transportation timetables from GTFS data.
+//!
+//! Data file ([`db::DataFile`]) is parsed once and departures of chosen stops
+//! are extracted into small databases ([`db::Database`] of [`db::Record`]s)
+//! which are then searched for upcoming departures by [`timetables::Timetables`]:
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use trs::db::DataFile;
+//! use trs::stop::Stop;
+//! use trs::timetables::{Timetables, TimetablesConfig};
+//!
+//! # async fn run() -> Result<(), trs::error::Error> {
+//! let data_file = DataFile::new(Path::new("/tmp"), "https://example.com/gtfs.zip".into());
+//! data_file.retrieve().await?;
+//! let gtfs = data_file.parse()?;
+//!
+//! let stop = gtfs.stops["U1072Z101P"].clone();
+//! let stops = vec![Stop::new(&gtfs, stop.clone(), &stop)?];
+//!
+//! let timetables = Timetables::new(TimetablesConfig::default());
+//!
+//! for departure in timetables.get_stop_departures(&stops) {
+//!     for record in departure.departures {
+//!         println!("{} {:?}", record.route, record.departure());
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Realtime info (delays, cancellations, service alerts) is added to the
+//! records by providers implementing [`realtime::RealtimeProvider`].
+//!
+//! The library does no printing nor reading of the standard input - that's
+//! up to the `trs` command line app.
+
+pub mod db;
+pub mod error;
+pub mod features;
+#[cfg(test)]
+mod fixtures;
+pub mod footpaths;
+pub mod ical;
+pub mod planner;
+pub mod realtime;
+pub mod stop;
+pub mod timetables;
@@ -1,19 +1,20 @@
 mod app;
 mod args;
 mod config;
-mod db;
-mod features;
-mod timetables;
+#[cfg(feature = "notify")]
+mod notify;
+mod server;
 mod ui;
 
 use app::App;
 
 #[tokio::main]
-pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
+pub async fn main() {
     // Args.
     let app_args = args::parse();
 
-    App::run(app_args).await?;
-
-    Ok(())
+    if let Err(e) = App::run(app_args).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
 }
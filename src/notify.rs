@@ -0,0 +1,310 @@
+use std::collections::{HashMap, HashSet};
+use std::slice;
+
+use chrono::{Duration, Local, NaiveDateTime};
+use tokio::time::sleep;
+use zbus::{proxy, zvariant::Value, Connection};
+
+use trs::db::Record;
+use trs::error::Error;
+use trs::stop::Stop;
+use trs::timetables::Timetables;
+
+use crate::ui::Ui;
+
+/// How often (seconds) departures are checked for changes (delays).
+const CHECK_INTERVAL: i64 = 60;
+/// Number of upcoming departures checked each time.
+const CANDIDATES: usize = 5;
+/// Longest walking time (seconds) to the stop.
+const MAX_WALK: i64 = 2 * 60 * 60;
+
+/// Desktop notifications service as described by freedesktop.org
+/// notification specification.
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, &Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+}
+
+/// Parses walking time like "6m", "90s" or "6" (minutes). Times longer
+/// than `MAX_WALK` are refused.
+pub fn parse_walk(value: &str) -> Option<Duration> {
+    let value = value.trim();
+
+    let walk = match value.strip_suffix('s') {
+        Some(seconds) => Duration::seconds(seconds.parse::<u32>().ok()?.into()),
+        None => {
+            let minutes = value.strip_suffix('m').unwrap_or(value);
+
+            Duration::minutes(minutes.parse::<u32>().ok()?.into())
+        }
+    };
+
+    Some(walk).filter(|walk| *walk <= Duration::seconds(MAX_WALK))
+}
+
+/// Watches departures from the stop (of the route) and raises a desktop
+/// notification when it's time to leave for the next departure. Runs
+/// until killed.
+pub async fn watch(
+    timetables: &Timetables,
+    ui: &Ui,
+    stop: &Stop,
+    route: Option<&str>,
+    walk: Duration,
+) -> Result<(), Error> {
+    // Session bus (DBUS_SESSION_BUS_ADDRESS).
+    let connection = Connection::session().await.map_err(dbus_error)?;
+    let proxy = NotificationsProxy::new(&connection)
+        .await
+        .map_err(dbus_error)?;
+    let mut notified = HashSet::new();
+
+    loop {
+        let now = Local::now().naive_local();
+        let mut departures = timetables.get_stop_departures(slice::from_ref(stop));
+
+        // Realtime (delay) info if available - keep watching by the timetable
+        // otherwise.
+        if let Err(e) = ui.update_schedule(&mut departures, &mut []).await {
+            Ui::info(&e.to_string());
+        }
+
+        let mut records = departures.pop().map(|d| d.departures).unwrap_or_default();
+        records.retain(|r| route.is_none_or(|route| r.route == route));
+
+        // Cancelled trips never come.
+        records.retain(|r| !r.realtime.as_ref().is_some_and(|r| r.is_canceled()));
+
+        records.truncate(CANDIDATES);
+        if let Err(e) = ui.spice_up(&mut [&mut records]).await {
+            Ui::info(&e.to_string());
+        }
+
+        // First departure we can still make and haven't been notified about.
+        let next = records
+            .iter()
+            .filter(|r| !notified.contains(&(r.trip_id.clone(), r.departure())))
+            .find_map(|r| {
+                let departure = r.departure()? + delay(r);
+
+                if departure - walk >= now - Duration::seconds(CHECK_INTERVAL) {
+                    Some((r, departure))
+                } else {
+                    None
+                }
+            });
+
+        let wait = match next {
+            Some((record, departure)) => {
+                let leave_at = departure - walk;
+
+                if leave_at <= now {
+                    send(&proxy, stop, record, departure)
+                        .await
+                        .map_err(dbus_error)?;
+                    notified.insert((record.trip_id.clone(), record.departure()));
+                    Duration::zero()
+                } else {
+                    leave_at - now
+                }
+            }
+            None => Duration::seconds(CHECK_INTERVAL),
+        };
+
+        // Check again for delays at least every minute.
+        let wait = wait.min(Duration::seconds(CHECK_INTERVAL));
+
+        if let Ok(wait) = wait.to_std() {
+            sleep(wait).await;
+        }
+    }
+}
+
+/// Converts D-Bus error (e.g. no session bus).
+fn dbus_error(e: zbus::Error) -> Error {
+    Error::Service(format!("Desktop notifications are not available: {}", e))
+}
+
+/// Returns realtime delay of the record (if known).
+fn delay(record: &Record) -> Duration {
+    record
+        .realtime
+        .as_ref()
+        .and_then(|r| r.delay)
+        .unwrap_or_else(Duration::zero)
+}
+
+/// Sends the "time to leave" notification.
+async fn send(
+    proxy: &NotificationsProxy<'_>,
+    stop: &Stop,
+    record: &Record,
+    departure: NaiveDateTime,
+) -> zbus::Result<u32> {
+    let summary = format!("Time to leave for {}", record.route);
+    let body = format!(
+        "{} departs from {} at {} (in {} min).",
+        match record.headsign() {
+            Some(headsign) => format!("{} → {}", record.route, headsign),
+            None => record.route.clone(),
+        },
+        stop.name,
+        departure.format("%H:%M"),
+        (departure - Local::now().naive_local()).num_minutes()
+    );
+    let urgency = Value::U8(2);
+    let mut hints = HashMap::new();
+    hints.insert("urgency", &urgency);
+
+    proxy
+        .notify("trs", 0, "", &summary, &body, &[], hints, -1)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use chrono::NaiveDate;
+    use tokio::net::UnixStream;
+    use zbus::{connection, interface, Guid};
+
+    use trs::db::{CustomCalendar, Database};
+
+    use super::*;
+
+    /// Notification daemon remembering summaries and bodies of the
+    /// notifications.
+    struct MockNotifications {
+        sent: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    #[interface(name = "org.freedesktop.Notifications")]
+    impl MockNotifications {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            _app_name: &str,
+            _replaces_id: u32,
+            _app_icon: &str,
+            summary: &str,
+            body: &str,
+            _actions: Vec<&str>,
+            _hints: HashMap<&str, Value<'_>>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let mut sent = self.sent.lock().unwrap();
+            sent.push((summary.to_string(), body.to_string()));
+
+            sent.len() as u32
+        }
+    }
+
+    fn stop() -> Stop {
+        Stop {
+            id: "S1".to_string(),
+            name: "Skloněná".to_string(),
+            terminating_stop: "Palmovka".to_string(),
+            wheelchair_boarding: None,
+            database: Database { records: vec![] },
+        }
+    }
+
+    fn record() -> Record {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 6).unwrap();
+
+        Record {
+            route: "136".to_string(),
+            route_id: None,
+            trip: "service".to_string(),
+            trip_id: "trip".to_string(),
+            calendar: CustomCalendar::day(date),
+            stop_time: Some(9 * 3600),
+            stop: "Skloněná".to_string(),
+            stop_id: None,
+            stop_sequence: None,
+            trip_headsign: Some("Palmovka".to_string()),
+            stop_headsign: None,
+            wheelchair_accessible: None,
+            bikes_allowed: None,
+            destination_time: None,
+            route_color: None,
+            route_text_color: None,
+            service_date: Some(date),
+            realtime: None,
+        }
+    }
+
+    #[test]
+    fn walking_time() {
+        assert_eq!(parse_walk("6m"), Some(Duration::minutes(6)));
+        assert_eq!(parse_walk("90s"), Some(Duration::seconds(90)));
+        assert_eq!(parse_walk(" 6 "), Some(Duration::minutes(6)));
+        assert_eq!(parse_walk("120m"), Some(Duration::hours(2)));
+        assert_eq!(parse_walk("121m"), None);
+        assert_eq!(parse_walk("99999999999999m"), None);
+        assert_eq!(parse_walk("-5m"), None);
+        assert_eq!(parse_walk("m"), None);
+    }
+
+    #[tokio::test]
+    async fn send_notification() {
+        let sent = Arc::new(Mutex::new(vec![]));
+        let (client, server) = UnixStream::pair().unwrap();
+
+        let server = connection::Builder::unix_stream(server)
+            .server(Guid::generate())
+            .unwrap()
+            .p2p()
+            .serve_at(
+                "/org/freedesktop/Notifications",
+                MockNotifications { sent: sent.clone() },
+            )
+            .unwrap()
+            .build();
+        let client = connection::Builder::unix_stream(client).p2p().build();
+        let (client, _server) = tokio::try_join!(client, server).unwrap();
+
+        let proxy = NotificationsProxy::new(&client).await.unwrap();
+        let record = record();
+        let id = send(&proxy, &stop(), &record, record.departure().unwrap())
+            .await
+            .unwrap();
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(sent[0].0, "Time to leave for 136");
+        assert!(sent[0]
+            .1
+            .starts_with("136 → Palmovka departs from Skloněná at 09:00"));
+    }
+
+    #[tokio::test]
+    async fn bus_not_available() {
+        let error = connection::Builder::address("unix:path=/nonexistent/bus")
+            .unwrap()
+            .build()
+            .await
+            .map_err(dbus_error)
+            .unwrap_err();
+
+        assert!(matches!(error, Error::Service(_)));
+        assert_eq!(error.exit_code(), 8);
+    }
+}
@@ -0,0 +1,504 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{Duration, NaiveDate};
+use gtfs_structures::{Gtfs, PickupDropOffType, Stop, TransferType, Trip};
+
+use crate::db::CustomCalendar;
+use crate::footpaths::{self, Walking};
+
+/// Maximum number of trips (vehicles) in one itinerary.
+const MAX_TRIPS: usize = 5;
+/// Minimum transfer time (seconds) at the same stop when
+/// the data file doesn't say otherwise.
+const DEFAULT_TRANSFER_TIME: u32 = 60;
+/// Unreachable stop arrival.
+const UNREACHABLE: u32 = u32::MAX;
+
+/// Elementary connection - a vehicle going from one stop to the next one.
+/// Times are in seconds from midnight of the planned day.
+struct Hop {
+    trip: usize,
+    from: usize,
+    to: usize,
+    departure: u32,
+    arrival: u32,
+    can_board: bool,
+    can_alight: bool,
+}
+
+/// How the stop was reached (journey pointer).
+#[derive(Clone, Copy)]
+enum Label {
+    Origin,
+    Ride { board: usize, alight: usize },
+    Walk { from: usize, duration: u32 },
+}
+
+/// One part of an itinerary.
+#[derive(Debug)]
+pub enum Leg {
+    Ride {
+        route: String,
+        headsign: Option<String>,
+        from: String,
+        to: String,
+        departure: u32,
+        arrival: u32,
+    },
+    Walk {
+        from: String,
+        to: String,
+        duration: u32,
+    },
+}
+
+/// Planned journey. Times are in seconds from midnight of the planned day.
+#[derive(Debug)]
+pub struct Itinerary {
+    pub departure: u32,
+    pub arrival: u32,
+    pub changes: usize,
+    pub legs: Vec<Leg>,
+}
+
+/// Journey planner over GTFS data of one day. Uses round based connection
+/// scan - each round allows one more trip so the result contains the fastest
+/// itineraries for each number of changes.
+pub struct Planner<'a> {
+    gtfs: &'a Gtfs,
+    stop_ids: Vec<&'a str>,
+    trips: Vec<&'a Trip>,
+    hops: Vec<Hop>,
+    transfer_times: Vec<u32>,
+    footpaths: Vec<Vec<(usize, u32)>>,
+}
+
+impl<'a> Planner<'a> {
+    /// Prepares (sorted) connections of all trips running on the given date.
+    /// Trips of the day before are included too since they can run after
+    /// midnight. Stops within walking distance are connected by footpaths.
+    pub fn new(gtfs: &'a Gtfs, date: NaiveDate, walking: &Walking) -> Self {
+        let mut stop_ids: Vec<&str> = gtfs.stops.keys().map(String::as_str).collect();
+        stop_ids.sort_unstable();
+        let index: HashMap<&str, usize> = stop_ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (*id, i))
+            .collect();
+
+        let mut trips = vec![];
+        let mut hops = vec![];
+
+        for (day, shift) in [(date - Duration::days(1), 24 * 60 * 60), (date, 0)] {
+            let mut services: HashMap<&str, bool> = HashMap::new();
+
+            for trip in gtfs.trips.values() {
+                let runs = *services
+                    .entry(trip.service_id.as_str())
+                    .or_insert_with(|| service_runs_on(gtfs, &trip.service_id, day));
+
+                if !runs {
+                    continue;
+                }
+
+                let trip_index = trips.len();
+                trips.push(trip);
+
+                for pair in trip.stop_times.windows(2) {
+                    let departure = pair[0].departure_time.or(pair[0].arrival_time);
+                    let arrival = pair[1].arrival_time.or(pair[1].departure_time);
+
+                    if let (Some(departure), Some(arrival)) = (departure, arrival) {
+                        // Skip what happened before midnight of the planned day.
+                        if departure < shift {
+                            continue;
+                        }
+
+                        hops.push(Hop {
+                            trip: trip_index,
+                            from: index[pair[0].stop.id.as_str()],
+                            to: index[pair[1].stop.id.as_str()],
+                            departure: departure - shift,
+                            arrival: arrival - shift,
+                            can_board: pair[0].pickup_type != PickupDropOffType::NotAvailable,
+                            can_alight: pair[1].drop_off_type != PickupDropOffType::NotAvailable,
+                        });
+                    }
+                }
+            }
+        }
+
+        hops.sort_by_key(|h| h.departure);
+
+        // Transfers within one stop and between stops (transfers.txt).
+        let mut transfer_times = vec![DEFAULT_TRANSFER_TIME; stop_ids.len()];
+        let mut footpaths: Vec<Vec<(usize, u32)>> = vec![vec![]; stop_ids.len()];
+        let mut defined = HashSet::new();
+
+        for (i, id) in stop_ids.iter().enumerate() {
+            for transfer in gtfs.stops[*id].transfers.iter() {
+                let to = match index.get(transfer.to_stop_id.as_str()) {
+                    Some(to) => *to,
+                    None => continue,
+                };
+                let time = match transfer.transfer_type {
+                    TransferType::Impossible => UNREACHABLE,
+                    TransferType::Timed => 0,
+                    _ => transfer.min_transfer_time.unwrap_or(DEFAULT_TRANSFER_TIME),
+                };
+
+                if to == i {
+                    transfer_times[i] = time;
+                } else if time != UNREACHABLE {
+                    footpaths[i].push((to, time));
+                }
+
+                defined.insert((i, to));
+            }
+        }
+
+        // Walking between nearby stops where transfers.txt says nothing.
+        let stops: Vec<&Stop> = stop_ids.iter().map(|id| gtfs.stops[*id].as_ref()).collect();
+
+        for (i, paths) in footpaths::generate(&stops, walking).into_iter().enumerate() {
+            for (to, duration) in paths {
+                if !defined.contains(&(i, to)) {
+                    footpaths[i].push((to, duration));
+                }
+            }
+        }
+
+        Self {
+            gtfs,
+            stop_ids,
+            trips,
+            hops,
+            transfer_times,
+            footpaths,
+        }
+    }
+
+    /// Finds stops by name - exact (case insensitive) matches are preferred
+    /// over stops containing the name.
+    pub fn find_stops(&self, name: &str) -> Vec<usize> {
+        let name = name.to_lowercase();
+        let stop_name = |i: &usize| self.gtfs.stops[self.stop_ids[*i]].name.to_lowercase();
+
+        let exact: Vec<usize> = (0..self.stop_ids.len())
+            .filter(|i| stop_name(i) == name)
+            .collect();
+
+        if !exact.is_empty() {
+            return exact;
+        }
+
+        (0..self.stop_ids.len())
+            .filter(|i| stop_name(i).contains(&name))
+            .collect()
+    }
+
+    /// Plans up to `count` itineraries between the stops departing at
+    /// `time` (seconds from midnight) or later. Itineraries are sorted by
+    /// arrival and number of changes.
+    pub fn plan(&self, from: &[usize], to: &[usize], time: u32, count: usize) -> Vec<Itinerary> {
+        let mut itineraries: Vec<Itinerary> = vec![];
+        let mut time = time;
+
+        // Each search returns the fastest itineraries for each number of changes.
+        // To get later alternatives search again departing after the first one.
+        while itineraries.len() < count {
+            let found = self.search(from, to, time);
+
+            let next_time = match found.iter().map(|i| i.departure).min() {
+                Some(departure) => departure.max(time) + 1,
+                None => break,
+            };
+            // Without any ride (same or nearby stop) a later search finds
+            // the same itinerary again.
+            let rideless = found
+                .iter()
+                .any(|i| !i.legs.iter().any(|l| matches!(l, Leg::Ride { .. })));
+
+            for itinerary in found {
+                let duplicate = itineraries.iter().any(|i| {
+                    i.departure == itinerary.departure
+                        && i.arrival == itinerary.arrival
+                        && i.changes == itinerary.changes
+                });
+
+                if !duplicate {
+                    itineraries.push(itinerary);
+                }
+            }
+
+            if rideless {
+                break;
+            }
+
+            time = next_time;
+        }
+
+        itineraries.sort_by_key(|i| (i.arrival, i.changes, u32::MAX - i.departure));
+        itineraries.truncate(count);
+
+        itineraries
+    }
+
+    /// Runs one search and returns Pareto optimal itineraries (arrival
+    /// vs number of changes).
+    fn search(&self, from: &[usize], to: &[usize], time: u32) -> Vec<Itinerary> {
+        let stops = self.stop_ids.len();
+        let mut arrivals = vec![vec![UNREACHABLE; stops]; MAX_TRIPS + 1];
+        let mut labels: Vec<Vec<Option<Label>>> = vec![vec![None; stops]; MAX_TRIPS + 1];
+
+        for stop in from {
+            arrivals[0][*stop] = time;
+            labels[0][*stop] = Some(Label::Origin);
+        }
+
+        // Walk from origin stops to the nearby ones.
+        for stop in from {
+            for (target, duration) in self.footpaths[*stop].iter() {
+                if time + duration < arrivals[0][*target] {
+                    arrivals[0][*target] = time + duration;
+                    labels[0][*target] = Some(Label::Walk {
+                        from: *stop,
+                        duration: *duration,
+                    });
+                }
+            }
+        }
+
+        let mut itineraries = vec![];
+        let mut best = UNREACHABLE;
+
+        // Skip connections departed before the requested time.
+        let first = self.hops.partition_point(|h| h.departure < time);
+
+        for round in 1..=MAX_TRIPS {
+            let (previous, current) = arrivals.split_at_mut(round);
+            let previous = &previous[round - 1];
+            let current = &mut current[0];
+            current.copy_from_slice(previous);
+
+            let mut boarded: HashMap<usize, usize> = HashMap::new();
+            let mut improved = vec![];
+
+            for (i, hop) in self.hops.iter().enumerate().skip(first) {
+                // Nothing can improve the best arrival any more.
+                if best <= hop.departure {
+                    break;
+                }
+
+                if !boarded.contains_key(&hop.trip) && hop.can_board {
+                    // Transfer time is needed only when changing vehicles.
+                    let transfer = match find_label(&labels, round - 1, hop.from) {
+                        Some((_, Label::Ride { .. })) => self.transfer_times[hop.from],
+                        _ => 0,
+                    };
+
+                    if previous[hop.from] != UNREACHABLE
+                        && previous[hop.from].saturating_add(transfer) <= hop.departure
+                    {
+                        boarded.insert(hop.trip, i);
+                    }
+                }
+
+                if let Some(board) = boarded.get(&hop.trip) {
+                    if hop.can_alight && hop.arrival < current[hop.to] {
+                        current[hop.to] = hop.arrival;
+                        labels[round][hop.to] = Some(Label::Ride {
+                            board: *board,
+                            alight: i,
+                        });
+                        improved.push(hop.to);
+                    }
+                }
+            }
+
+            // Walk from stops reached in this round.
+            for stop in improved {
+                for (target, duration) in self.footpaths[stop].iter() {
+                    let arrival = current[stop] + duration;
+
+                    if arrival < current[*target] {
+                        current[*target] = arrival;
+                        labels[round][*target] = Some(Label::Walk {
+                            from: stop,
+                            duration: *duration,
+                        });
+                    }
+                }
+            }
+
+            // Better arrival with one more trip means new itinerary.
+            if let Some(target) = to.iter().copied().min_by_key(|t| current[*t]) {
+                if current[target] < best {
+                    best = current[target];
+                    itineraries.push(self.build_itinerary(&labels, round, target, time));
+                }
+            }
+        }
+
+        itineraries
+    }
+
+    /// Follows journey pointers back from the target stop to the origin.
+    fn build_itinerary(
+        &self,
+        labels: &[Vec<Option<Label>>],
+        round: usize,
+        target: usize,
+        time: u32,
+    ) -> Itinerary {
+        let mut legs = vec![];
+        let mut stop = target;
+        let mut round = round;
+
+        while let Some((r, label)) = find_label(labels, round, stop) {
+            match label {
+                Label::Origin => break,
+                Label::Walk { from, duration } => {
+                    legs.push(Leg::Walk {
+                        from: self.stop_name(from),
+                        to: self.stop_name(stop),
+                        duration,
+                    });
+                    stop = from;
+                    round = r;
+                }
+                Label::Ride { board, alight } => {
+                    let (board, alight) = (&self.hops[board], &self.hops[alight]);
+                    let trip = self.trips[board.trip];
+
+                    legs.push(Leg::Ride {
+                        route: self
+                            .gtfs
+                            .routes
+                            .get(&trip.route_id)
+                            .map_or_else(|| trip.route_id.clone(), |r| r.short_name.clone()),
+                        headsign: trip.trip_headsign.clone(),
+                        from: self.stop_name(board.from),
+                        to: self.stop_name(alight.to),
+                        departure: board.departure,
+                        arrival: alight.arrival,
+                    });
+                    stop = board.from;
+                    round = r - 1;
+                }
+            }
+        }
+
+        legs.reverse();
+
+        let rides = legs
+            .iter()
+            .filter(|l| matches!(l, Leg::Ride { .. }))
+            .count();
+        let departure = legs.iter().find_map(|l| match l {
+            Leg::Ride { departure, .. } => Some(*departure),
+            _ => None,
+        });
+        let arrival = legs.iter().rev().find_map(|l| match l {
+            Leg::Ride { arrival, .. } => Some(*arrival),
+            _ => None,
+        });
+        // Walking after the last ride.
+        let walk_after: u32 = legs
+            .iter()
+            .rev()
+            .take_while(|l| matches!(l, Leg::Walk { .. }))
+            .map(|l| match l {
+                Leg::Walk { duration, .. } => *duration,
+                _ => 0,
+            })
+            .sum();
+
+        // Itineraries without any ride start at the requested time.
+        Itinerary {
+            departure: departure.unwrap_or(time),
+            arrival: arrival.unwrap_or(time) + walk_after,
+            changes: rides.saturating_sub(1),
+            legs,
+        }
+    }
+
+    fn stop_name(&self, stop: usize) -> String {
+        self.gtfs.stops[self.stop_ids[stop]].name.clone()
+    }
+}
+
+/// Returns the label (and its round) which set the stop arrival in the given
+/// round - it can be set in any of previous rounds.
+fn find_label(labels: &[Vec<Option<Label>>], round: usize, stop: usize) -> Option<(usize, Label)> {
+    (0..=round)
+        .rev()
+        .find_map(|r| labels[r][stop].map(|l| (r, l)))
+}
+
+/// Checks if the service runs on the given date (see
+/// `CustomCalendar::runs_on()`).
+fn service_runs_on(gtfs: &Gtfs, service_id: &str, date: NaiveDate) -> bool {
+    CustomCalendar::service(gtfs, service_id).is_ok_and(|c| c.runs_on(date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{date, gtfs};
+
+    #[test]
+    fn plan_same_stop() {
+        let gtfs = gtfs("plan-same-stop");
+        let planner = Planner::new(&gtfs, date(6), &Walking::default());
+        let alpha = planner.find_stops("Alpha");
+
+        let itineraries = planner.plan(&alpha, &alpha, 9 * 3600, 3);
+
+        assert_eq!(itineraries.len(), 1);
+        assert_eq!(itineraries[0].departure, 9 * 3600);
+        assert_eq!(itineraries[0].arrival, 9 * 3600);
+        assert!(itineraries[0].legs.is_empty());
+    }
+
+    #[test]
+    fn plan_walk_only() {
+        let gtfs = gtfs("plan-walk-only");
+        let walking = Walking::default();
+        let planner = Planner::new(&gtfs, date(6), &walking);
+
+        let itineraries = planner.plan(
+            &planner.find_stops("Alpha"),
+            &planner.find_stops("Beta"),
+            9 * 3600,
+            3,
+        );
+        let walk = walking.duration(footpaths::distance((50.0, 14.0), (50.001, 14.0)));
+
+        assert_eq!(itineraries.len(), 1);
+        assert_eq!(itineraries[0].departure, 9 * 3600);
+        assert_eq!(itineraries[0].arrival, 9 * 3600 + walk);
+        assert!(matches!(
+            itineraries[0].legs.as_slice(),
+            [Leg::Walk { duration, .. }] if *duration == walk
+        ));
+    }
+
+    #[test]
+    fn plan_ride() {
+        let gtfs = gtfs("plan-ride");
+        let planner = Planner::new(&gtfs, date(6), &Walking::default());
+
+        let itineraries = planner.plan(
+            &planner.find_stops("Alpha"),
+            &planner.find_stops("Gamma"),
+            9 * 3600,
+            3,
+        );
+
+        assert_eq!(itineraries.len(), 1);
+        assert_eq!(itineraries[0].departure, 9 * 3600 + 600);
+        assert_eq!(itineraries[0].arrival, 9 * 3600 + 1800);
+        assert_eq!(itineraries[0].changes, 0);
+    }
+}
@@ -0,0 +1,611 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration as StdDuration;
+
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDateTime};
+use reqwest::{header, Client, ClientBuilder, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+
+use crate::db::Record;
+use crate::error::Error;
+#[cfg(feature = "gtfs-rt")]
+use crate::features::gtfs_rt::GtfsRt;
+#[cfg(feature = "prague")]
+use crate::features::prague::{ApiKey, Golemio};
+use crate::timetables::{ConnectionDeparture, Departure};
+
+/// Timeout of one realtime request (connecting included).
+const REQUEST_TIMEOUT: StdDuration = StdDuration::from_secs(5);
+const CONNECT_TIMEOUT: StdDuration = StdDuration::from_secs(3);
+/// How many times is a failed request sent (the first attempt included).
+const MAX_ATTEMPTS: u32 = 3;
+/// Backoff before the first retry, doubled with each following one.
+const BACKOFF: StdDuration = StdDuration::from_millis(500);
+/// Longest wait before a retry - longer `Retry-After` gives up right away.
+const MAX_BACKOFF: StdDuration = StdDuration::from_secs(8);
+
+/// Source of realtime info (delays, cancellations, alerts...) of a city
+/// or agency. Providers are selected per feed in the config (see
+/// `RealtimeFeed`).
+#[async_trait]
+pub trait RealtimeProvider: Send + Sync {
+    /// Enriches the records with realtime info. Called once for departures
+    /// of all stops and connections so providers can share their requests
+    /// among the records. The records are a wider window (including
+    /// departures scheduled in the past) which is cut to the limit by
+    /// the expected departure afterwards.
+    async fn enrich(&self, records: &mut [&mut Record]) -> Result<(), Error>;
+
+    /// Applies changes of the schedule (cancelled, skipped or added trips)
+    /// to departures before they are cut to the limit.
+    async fn update_schedule(
+        &self,
+        _departures: &mut [Departure<'_>],
+        _connections: &mut [ConnectionDeparture<'_>],
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns service alerts in effect.
+    async fn alerts(&self) -> Result<Vec<ServiceAlert>, Error> {
+        Ok(vec![])
+    }
+}
+
+/// Realtime feed as set in the config:
+///
+/// ```yaml
+/// realtime:
+///   - provider: golemio
+///     api_key_command: pass show golemio
+///   - provider: gtfs-rt
+///     trip_updates: https://example.com/gtfs-rt/trip-updates.pb
+///     alerts: https://example.com/gtfs-rt/alerts.pb
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "provider", rename_all = "kebab-case")]
+pub enum RealtimeFeed {
+    /// Golemio API of Prague integrated transport (`prague` feature).
+    Golemio {
+        /// Vehicle positions URL with `{route}` placeholder (to use
+        /// a mirror or a mock server instead of the API).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+        /// Stops URL with `{ids}` placeholder (stop names are looked up
+        /// there).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stops_url: Option<String>,
+        /// Departure boards of the stops are used besides vehicle positions
+        /// (URL with `{stop}` placeholder can be set).
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        departure_boards: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        departure_boards_url: Option<String>,
+        /// API key - given right away, in a file or printed by a shell
+        /// command (`GOLEMIO_API_KEY` variable when none is set).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        api_key: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        api_key_file: Option<PathBuf>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        api_key_command: Option<String>,
+        /// PEM file with CA certificate trusted besides the system ones.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ca_cert: Option<PathBuf>,
+    },
+    /// GTFS-Realtime TripUpdates and Alerts feeds - URL or path to local
+    /// file (`gtfs-rt` feature).
+    GtfsRt {
+        trip_updates: Option<String>,
+        alerts: Option<String>,
+    },
+}
+
+impl RealtimeFeed {
+    /// Golemio feed with default options.
+    pub fn golemio() -> Self {
+        Self::Golemio {
+            url: None,
+            stops_url: None,
+            departure_boards: false,
+            departure_boards_url: None,
+            api_key: None,
+            api_key_file: None,
+            api_key_command: None,
+            ca_cert: None,
+        }
+    }
+
+    /// Creates provider of the feed caching its responses in the directory
+    /// (if given). Fails when trs was built without the feature of the
+    /// provider.
+    #[cfg_attr(not(feature = "prague"), allow(unused_variables))]
+    pub fn provider(&self, cache_dir: Option<PathBuf>) -> Result<Box<dyn RealtimeProvider>, Error> {
+        match self {
+            #[cfg(feature = "prague")]
+            Self::Golemio {
+                url,
+                stops_url,
+                departure_boards,
+                departure_boards_url,
+                api_key,
+                api_key_file,
+                api_key_command,
+                ca_cert,
+            } => {
+                let api_key = match (api_key, api_key_file, api_key_command) {
+                    (Some(key), _, _) => ApiKey::Value(key.clone()),
+                    (_, Some(path), _) => ApiKey::File(path.clone()),
+                    (_, _, Some(command)) => ApiKey::Command(command.clone()),
+                    _ => ApiKey::Env,
+                };
+
+                Ok(Box::new(
+                    Golemio::new(cache_dir)
+                        .with_url(url.clone())
+                        .with_stops_url(stops_url.clone())
+                        .with_departure_boards(*departure_boards)
+                        .with_boards_url(departure_boards_url.clone())
+                        .with_api_key(api_key)
+                        .with_ca_cert(ca_cert.clone()),
+                ))
+            }
+            #[cfg(feature = "gtfs-rt")]
+            Self::GtfsRt {
+                trip_updates,
+                alerts,
+            } => Ok(Box::new(GtfsRt::new(trip_updates.clone(), alerts.clone()))),
+            #[allow(unreachable_patterns)]
+            _ => Err(Error::Config(format!(
+                "Realtime provider \"{}\" is not available (feature \"{}\" is not enabled).",
+                self.name(),
+                self.feature()
+            ))),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Golemio { .. } => "golemio",
+            Self::GtfsRt { .. } => "gtfs-rt",
+        }
+    }
+
+    /// Cargo feature the provider is built with.
+    fn feature(&self) -> &'static str {
+        match self {
+            Self::Golemio { .. } => "prague",
+            Self::GtfsRt { .. } => "gtfs-rt",
+        }
+    }
+}
+
+/// Returns HTTP client builder with timeouts of realtime requests.
+pub fn client_builder() -> ClientBuilder {
+    Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .connect_timeout(CONNECT_TIMEOUT)
+}
+
+/// Sends the request and retries it (with capped exponential backoff) when
+/// it times out, the connection fails or the server is overloaded (HTTP 429
+/// or 5xx). Delay requested by `Retry-After` header is honored. Gives up
+/// after a few attempts so an outage doesn't hang the app.
+pub async fn send_with_retry(request: RequestBuilder) -> Result<Response, Error> {
+    let mut backoff = BACKOFF;
+    let mut attempt = 1;
+
+    loop {
+        let this_request = request
+            .try_clone()
+            .ok_or_else(|| Error::Realtime("Request can't be retried.".to_string()))?;
+
+        let (failure, wait) = match this_request.send().await {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) if is_transient(response.status()) => (
+                format!("Server responded with {}", response.status()),
+                retry_after(&response).unwrap_or(backoff),
+            ),
+            Ok(response) => {
+                return Err(Error::Realtime(format!(
+                    "Server responded with {}.",
+                    response.status()
+                )))
+            }
+            Err(e) if e.is_timeout() || e.is_connect() => (e.to_string(), backoff),
+            Err(e) => return Err(realtime_error(e)),
+        };
+
+        if attempt >= MAX_ATTEMPTS || wait > MAX_BACKOFF {
+            return Err(Error::Realtime(format!(
+                "{} (attempt {}).",
+                failure, attempt
+            )));
+        }
+
+        sleep(wait).await;
+        backoff = next_backoff(backoff);
+        attempt += 1;
+    }
+}
+
+/// Doubles the backoff up to `MAX_BACKOFF`.
+fn next_backoff(backoff: StdDuration) -> StdDuration {
+    (backoff * 2).min(MAX_BACKOFF)
+}
+
+/// Converts any error of realtime data fetching.
+pub fn realtime_error(e: impl fmt::Display) -> Error {
+    Error::Realtime(e.to_string())
+}
+
+/// Whether the response status is worth retrying.
+fn is_transient(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Parses `Retry-After` header given in seconds.
+fn retry_after(response: &Response) -> Option<StdDuration> {
+    response
+        .headers()
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(StdDuration::from_secs)
+}
+
+/// Realtime status of the departure.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TripStatus {
+    #[default]
+    Scheduled,
+    Canceled,
+    /// Trip not in the timetable (extra trip).
+    Added,
+}
+
+/// Realtime info of the departure.
+#[derive(Debug, Clone, Default)]
+pub struct Realtime {
+    /// Predicted departure.
+    pub departure: Option<NaiveDateTime>,
+    /// Delay (negative when ahead of the schedule).
+    pub delay: Option<Duration>,
+    pub status: TripStatus,
+    pub air_conditioned: Option<bool>,
+    /// Platform (or stand) the trip departs from.
+    pub platform: Option<String>,
+    /// Vehicle serving the trip (if known).
+    pub vehicle: Option<Vehicle>,
+}
+
+/// Vehicle serving the trip and where it is now.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Vehicle {
+    /// Type like bus or tram.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub vehicle_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registration_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub low_floor: Option<bool>,
+    /// The last stop passed (ID and name).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_stop_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_stop: Option<String>,
+    /// Number of stops till the departure stop.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stops_away: Option<u16>,
+}
+
+impl Realtime {
+    pub fn is_canceled(&self) -> bool {
+        self.status == TripStatus::Canceled
+    }
+}
+
+/// Formats realtime info like: `❄ [13:22 +2 min] (platform B, bus 8523,
+/// now at Palmovka, 3 stops away)`, `[CANCELED]`
+impl fmt::Display for Realtime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.air_conditioned == Some(true) {
+            write!(f, " ❄")?;
+        }
+
+        let minutes = self.delay.map(|d| d.num_minutes());
+
+        match (self.status, self.departure, minutes) {
+            (TripStatus::Canceled, _, _) => return write!(f, " [CANCELED]"),
+            (TripStatus::Added, _, _) => write!(f, " [extra trip]")?,
+            (_, Some(departure), Some(0)) => write!(f, " [{} on time]", departure.format("%H:%M"))?,
+            (_, Some(departure), Some(minutes)) => {
+                write!(f, " [{} {:+} min]", departure.format("%H:%M"), minutes)?
+            }
+            (_, None, Some(minutes)) if minutes != 0 => write!(f, " [{:+} min]", minutes)?,
+            _ => {}
+        }
+
+        let parts: Vec<String> = self
+            .platform
+            .iter()
+            .map(|platform| format!("platform {}", platform))
+            .chain(self.vehicle.iter().flat_map(Vehicle::describe))
+            .collect();
+
+        if parts.is_empty() {
+            Ok(())
+        } else {
+            write!(f, " ({})", parts.join(", "))
+        }
+    }
+}
+
+impl Vehicle {
+    /// Describes the vehicle like: tram 9412, now at Náměstí Míru, 3 stops away
+    fn describe(&self) -> Vec<String> {
+        let mut parts = vec![];
+
+        match (&self.vehicle_type, &self.registration_number) {
+            (Some(vehicle_type), Some(number)) => {
+                parts.push(format!("{} {}", vehicle_type, number))
+            }
+            (Some(vehicle_type), None) => parts.push(vehicle_type.clone()),
+            (None, Some(number)) => parts.push(format!("vehicle {}", number)),
+            (None, None) => {}
+        }
+        if let Some(last_stop) = &self.last_stop {
+            parts.push(format!("now at {}", last_stop));
+        }
+        match self.stops_away {
+            Some(1) => parts.push("1 stop away".to_string()),
+            Some(stops) if stops > 1 => parts.push(format!("{} stops away", stops)),
+            _ => {}
+        }
+
+        parts
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Unknown,
+    Info,
+    Warning,
+    Severe,
+}
+
+/// Route, trip or stop (or their combination) affected by an alert.
+/// Empty entity means everything is affected.
+#[derive(Debug, Clone, Default)]
+pub struct AffectedEntity {
+    pub route_id: Option<String>,
+    pub trip_id: Option<String>,
+    pub stop_id: Option<String>,
+}
+
+/// Service alert currently in effect (active period containing now).
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceAlert {
+    pub header: String,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    pub severity: Severity,
+    pub active_from: Option<NaiveDateTime>,
+    pub active_until: Option<NaiveDateTime>,
+    #[serde(skip)]
+    pub informed_entity: Vec<AffectedEntity>,
+}
+
+impl ServiceAlert {
+    /// Whether the alert affects the stop (if given) or any of the records
+    /// (departures) - all fields of an affected entity have to match.
+    pub fn affects(&self, stop_id: Option<&str>, records: &[Record]) -> bool {
+        self.informed_entity.iter().any(|entity| {
+            if entity.route_id.is_none() && entity.trip_id.is_none() {
+                return match &entity.stop_id {
+                    Some(id) => {
+                        stop_id == Some(id.as_str())
+                            || records.iter().any(|r| r.stop_id.as_ref() == Some(id))
+                    }
+                    None => true,
+                };
+            }
+
+            records.iter().any(|r| {
+                entity
+                    .stop_id
+                    .as_ref()
+                    .is_none_or(|id| r.stop_id.as_ref() == Some(id))
+                    && entity
+                        .route_id
+                        .as_ref()
+                        .is_none_or(|id| r.route_id.as_ref() == Some(id))
+                    && entity.trip_id.as_ref().is_none_or(|id| *id == r.trip_id)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::time::Instant;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::fixtures::{mock_server, record};
+
+    async fn get(url: &str) -> Result<Response, Error> {
+        send_with_retry(client_builder().build().unwrap().get(url)).await
+    }
+
+    #[tokio::test]
+    async fn retries_until_success() {
+        let (url, hits) = mock_server(&["503 Service Unavailable", "200 OK"]).await;
+        let start = Instant::now();
+
+        assert!(get(&url).await.is_ok());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert!(start.elapsed() >= BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let (url, hits) = mock_server(&["500 Internal Server Error"]).await;
+        let start = Instant::now();
+
+        let error = get(&url).await.unwrap_err();
+
+        assert!(matches!(error, Error::Realtime(msg) if msg.contains("attempt 3")));
+        assert_eq!(hits.load(Ordering::SeqCst), MAX_ATTEMPTS as usize);
+        // Backoff before the second and the third attempt.
+        assert!(start.elapsed() >= BACKOFF + next_backoff(BACKOFF));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let (url, hits) = mock_server(&["404 Not Found"]).await;
+
+        assert!(matches!(get(&url).await, Err(Error::Realtime(_))));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn honors_retry_after() {
+        let (url, hits) = mock_server(&["429 Too Many Requests\r\nRetry-After: 1", "200 OK"]).await;
+        let start = Instant::now();
+
+        assert!(get(&url).await.is_ok());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert!(start.elapsed() >= StdDuration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_long_retry_after() {
+        let (url, hits) =
+            mock_server(&["503 Service Unavailable\r\nRetry-After: 3600", "200 OK"]).await;
+        let start = Instant::now();
+
+        let error = get(&url).await.unwrap_err();
+
+        assert!(matches!(error, Error::Realtime(msg) if msg.contains("attempt 1")));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        assert!(start.elapsed() < MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn retries_failed_connections() {
+        // Address of a closed port.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+
+        let error = get(&url).await.unwrap_err();
+
+        assert!(matches!(error, Error::Realtime(msg) if msg.contains("attempt 3")));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let mut backoff = BACKOFF;
+        let mut waits = vec![];
+
+        for _ in 0..6 {
+            waits.push(backoff);
+            backoff = next_backoff(backoff);
+        }
+
+        assert_eq!(
+            waits,
+            [500, 1000, 2000, 4000, 8000, 8000].map(StdDuration::from_millis)
+        );
+        assert_eq!(next_backoff(MAX_BACKOFF), MAX_BACKOFF);
+    }
+
+    fn alert(informed_entity: Vec<AffectedEntity>) -> ServiceAlert {
+        ServiceAlert {
+            header: "Detour".to_string(),
+            description: None,
+            url: None,
+            severity: Severity::Unknown,
+            active_from: None,
+            active_until: None,
+            informed_entity,
+        }
+    }
+
+    fn entity(
+        route_id: Option<&str>,
+        trip_id: Option<&str>,
+        stop_id: Option<&str>,
+    ) -> AffectedEntity {
+        AffectedEntity {
+            route_id: route_id.map(str::to_string),
+            trip_id: trip_id.map(str::to_string),
+            stop_id: stop_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn alert_affecting_stop() {
+        let alert = alert(vec![entity(None, None, Some("S5"))]);
+
+        assert!(alert.affects(Some("S5"), &[]));
+        assert!(alert.affects(None, &[record()]));
+        assert!(!alert.affects(Some("S6"), &[]));
+    }
+
+    #[test]
+    fn alert_affecting_route() {
+        let alert = alert(vec![entity(Some("L136"), None, None)]);
+        let other = Record {
+            route_id: Some("L177".to_string()),
+            ..record()
+        };
+
+        assert!(alert.affects(Some("S6"), &[other.clone(), record()]));
+        assert!(!alert.affects(Some("S5"), &[other]));
+        assert!(!alert.affects(Some("S5"), &[]));
+    }
+
+    #[test]
+    fn alert_affecting_trip() {
+        let alert = alert(vec![entity(None, Some("trip"), None)]);
+        let other = Record {
+            trip_id: "other".to_string(),
+            ..record()
+        };
+
+        assert!(alert.affects(None, &[record()]));
+        assert!(!alert.affects(Some("S5"), &[other]));
+    }
+
+    #[test]
+    fn alert_affecting_route_at_stop() {
+        let alert = alert(vec![entity(Some("L136"), None, Some("S6"))]);
+
+        assert!(!alert.affects(Some("S6"), &[record()]));
+        assert!(alert.affects(
+            None,
+            &[Record {
+                stop_id: Some("S6".to_string()),
+                ..record()
+            }]
+        ));
+    }
+
+    #[test]
+    fn alert_affecting_everything() {
+        assert!(alert(vec![entity(None, None, None)]).affects(None, &[]));
+        assert!(!alert(vec![]).affects(Some("S5"), &[record()]));
+    }
+}
@@ -0,0 +1,435 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::slice;
+use std::time::SystemTime;
+
+use clap::ArgMatches;
+use hyper::header::{ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use tokio::task::{self, LocalSet};
+
+use trs::error::Error;
+use trs::timetables::{Timetables, TimetablesConfig};
+
+use crate::args;
+use crate::config::Config;
+use crate::ui::Ui;
+
+/// Stop as listed by /stops endpoint.
+#[derive(Serialize)]
+struct JsonStopInfo<'a> {
+    id: &'a str,
+    name: &'a str,
+    terminating_stop: &'a str,
+    wheelchair_boarding: Option<bool>,
+}
+
+/// Error response body.
+#[derive(Serialize)]
+struct JsonError {
+    error: String,
+}
+
+/// Maximum number of departures per stop (`limit` query param is clamped).
+const MAX_LIMIT: usize = 100;
+
+/// Error response with its HTTP status.
+type HttpError = (StatusCode, String);
+
+/// Executor running connection tasks on the current thread since the config
+/// (and the UI) are shared via `Rc`.
+#[derive(Clone, Copy)]
+struct LocalExecutor;
+
+impl<F> hyper::rt::Executor<F> for LocalExecutor
+where
+    F: Future + 'static,
+{
+    fn execute(&self, future: F) {
+        task::spawn_local(future);
+    }
+}
+
+/// Local HTTP server exposing departures as JSON API:
+///
+/// GET /stops                            user's stops
+/// GET /departures?stop=<query>&limit=N  departures (like `trs -f json`)
+/// GET /departures/<stop_id>?limit=N     departures from one stop
+///
+/// Config (with the database and realtime feeds) is reloaded whenever
+/// the config file changes so refreshing the data file by `trs -r` doesn't
+/// need a restart.
+pub struct ApiServer {
+    config: RefCell<Rc<Config>>,
+    modified: Cell<Option<SystemTime>>,
+    timetables_config: TimetablesConfig,
+    ui: RefCell<Rc<Ui>>,
+    args: ArgMatches,
+}
+
+impl ApiServer {
+    pub fn new(config: Rc<Config>, args: ArgMatches) -> Result<Self, Error> {
+        Ok(Self {
+            modified: Cell::new(Config::modified()),
+            timetables_config: args::timetables_config(&args)?,
+            ui: RefCell::new(Rc::new(Self::ui(&config, &args)?)),
+            config: RefCell::new(config),
+            args,
+        })
+    }
+
+    /// Builds UI with realtime providers of the config.
+    fn ui(config: &Config, args: &ArgMatches) -> Result<Ui, Error> {
+        Ok(Ui::new(args.clone())?.with_realtime(config.realtime_providers()))
+    }
+
+    /// Listens on the address and serves requests until killed.
+    pub async fn serve(self, address: SocketAddr) -> Result<(), Error> {
+        let server = Rc::new(self);
+
+        LocalSet::new()
+            .run_until(async move {
+                let make_service = make_service_fn(move |_| {
+                    let server = Rc::clone(&server);
+
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |request| {
+                            let server = Rc::clone(&server);
+
+                            async move { Ok::<_, Infallible>(server.handle(request).await) }
+                        }))
+                    }
+                });
+
+                let server = Server::try_bind(&address)
+                    .map_err(server_error)?
+                    .executor(LocalExecutor)
+                    .serve(make_service);
+                Ui::info(&format!("Listening on http://{}", address));
+
+                server.await.map_err(server_error)
+            })
+            .await
+    }
+
+    /// Routes the request and converts the result to JSON response.
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let result = match request.method() {
+            &Method::GET => self.route(&request).await,
+            _ => Err((
+                StatusCode::METHOD_NOT_ALLOWED,
+                "Only GET requests are supported.".to_string(),
+            )),
+        };
+
+        let (status, body) = match result {
+            Ok(body) => (StatusCode::OK, body),
+            Err((status, error)) => (status, serde_json::to_string(&JsonError { error }).unwrap()),
+        };
+
+        Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn route(&self, request: &Request<Body>) -> Result<String, HttpError> {
+        self.reload().await.map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Couldn't load config: {}", e),
+            )
+        })?;
+
+        let query = request
+            .uri()
+            .query()
+            .map(|q| form_urlencoded::parse(q.as_bytes()).into_owned().collect())
+            .unwrap_or_default();
+        let path = percent_decode_str(request.uri().path()).decode_utf8_lossy();
+        let segments = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<&str>>();
+
+        match segments[..] {
+            ["stops"] => Ok(self.stops()),
+            ["departures"] => self.departures(&query).await,
+            ["departures", stop_id] => self.stop_departures(stop_id, &query).await,
+            _ => Err((StatusCode::NOT_FOUND, "Not found.".to_string())),
+        }
+    }
+
+    /// Reloads the config when the config file has changed since the last
+    /// load (e.g. the database has been refreshed).
+    async fn reload(&self) -> Result<(), Error> {
+        let modified = Config::modified();
+
+        if modified != self.modified.get() {
+            let config = Config::load().await?;
+            *self.ui.borrow_mut() = Rc::new(Self::ui(&config, &self.args)?);
+            *self.config.borrow_mut() = Rc::new(config);
+            self.modified.set(modified);
+        }
+
+        Ok(())
+    }
+
+    /// GET /stops
+    fn stops(&self) -> String {
+        let config = self.config.borrow();
+        let stops = config
+            .stops
+            .iter()
+            .map(|stop| JsonStopInfo {
+                id: &stop.id,
+                name: &stop.name,
+                terminating_stop: &stop.terminating_stop,
+                wheelchair_boarding: stop.wheelchair_boarding,
+            })
+            .collect::<Vec<JsonStopInfo>>();
+
+        serde_json::to_string(&stops).unwrap()
+    }
+
+    /// GET /departures - all stops and connections or only the stop given
+    /// by `stop` query param (ID or name).
+    async fn departures(&self, query: &HashMap<String, String>) -> Result<String, HttpError> {
+        let limit = self.limit(query)?;
+        let config = Rc::clone(&self.config.borrow());
+        let ui = Rc::clone(&self.ui.borrow());
+        let timetables = Timetables::new(TimetablesConfig {
+            limit,
+            ..self.timetables_config.clone()
+        });
+
+        let mut departures = timetables.get_stop_departures(&config.stops);
+        let mut connections = timetables.get_connections(&config.connections);
+
+        if let Some(stop_query) = query.get("stop") {
+            let stop = config.find_stop(stop_query).ok_or((
+                StatusCode::NOT_FOUND,
+                format!("Stop \"{}\" is not among your stops.", stop_query),
+            ))?;
+            departures.retain(|d| std::ptr::eq(d.stop, stop));
+            connections.clear();
+        }
+
+        ui.prepare(&mut departures, &mut connections, limit)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+        Ok(serde_json::to_string(&Ui::json_output(&departures, &connections)).unwrap())
+    }
+
+    /// GET /departures/<stop_id>
+    async fn stop_departures(
+        &self,
+        stop_id: &str,
+        query: &HashMap<String, String>,
+    ) -> Result<String, HttpError> {
+        let limit = self.limit(query)?;
+        let config = Rc::clone(&self.config.borrow());
+        let ui = Rc::clone(&self.ui.borrow());
+        let stop = config.stops.iter().find(|s| s.id == stop_id).ok_or((
+            StatusCode::NOT_FOUND,
+            format!("Stop \"{}\" is not among your stops.", stop_id),
+        ))?;
+        let timetables = Timetables::new(TimetablesConfig {
+            limit,
+            ..self.timetables_config.clone()
+        });
+
+        let mut departures = timetables.get_stop_departures(slice::from_ref(stop));
+        ui.prepare(&mut departures, &mut [], limit)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+
+        let departure = departures.first().ok_or((
+            StatusCode::NOT_FOUND,
+            format!("Stop \"{}\" is not among your stops.", stop_id),
+        ))?;
+
+        Ok(serde_json::to_string(&Ui::json_stop(departure)).unwrap())
+    }
+
+    /// Returns the limit given by `limit` query param (up to `MAX_LIMIT`)
+    /// or the default one.
+    fn limit(&self, query: &HashMap<String, String>) -> Result<usize, HttpError> {
+        match query.get("limit") {
+            Some(limit) => limit
+                .parse()
+                .map(|limit: usize| limit.min(MAX_LIMIT))
+                .map_err(|_| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid limit \"{}\".", limit),
+                    )
+                }),
+            None => Ok(self.timetables_config.limit),
+        }
+    }
+}
+
+/// Converts HTTP server error (e.g. address already in use).
+fn server_error(e: hyper::Error) -> Error {
+    Error::Service(format!("HTTP server failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use chrono::{Duration, Local};
+    use trs::db::{CustomCalendar, Database, Record};
+    use trs::stop::Stop;
+
+    use super::*;
+
+    /// Stop departing every minute from noon (more than `MAX_LIMIT` times).
+    fn stop() -> Stop {
+        let today = Local::now().naive_local().date();
+        let calendar = CustomCalendar {
+            start_date: today - Duration::days(1),
+            end_date: today + Duration::days(7),
+            ..CustomCalendar::day(today)
+        };
+        let records = (0..(MAX_LIMIT as u32 + 50))
+            .map(|minute| Record {
+                route: "136".to_string(),
+                route_id: None,
+                trip: "service".to_string(),
+                trip_id: format!("trip{}", minute),
+                calendar: calendar.clone(),
+                stop_time: Some(12 * 3600 + minute * 60),
+                stop: "Skloněná".to_string(),
+                stop_id: Some("S5".to_string()),
+                stop_sequence: None,
+                trip_headsign: Some("Palmovka".to_string()),
+                stop_headsign: None,
+                wheelchair_accessible: None,
+                bikes_allowed: None,
+                destination_time: None,
+                route_color: None,
+                route_text_color: None,
+                service_date: None,
+                realtime: None,
+            })
+            .collect();
+
+        Stop {
+            id: "S5".to_string(),
+            name: "Skloněná".to_string(),
+            terminating_stop: "Palmovka".to_string(),
+            wheelchair_boarding: None,
+            database: Database { records },
+        }
+    }
+
+    /// Requests the server (with the stop only and no realtime feeds)
+    /// and returns the response status and JSON body.
+    async fn get(uri: &str) -> (StatusCode, serde_json::Value) {
+        let mut config: Config = serde_yaml::from_str(
+            "data_file_url: ''\ndata_file_path: ''\nuser_stops: []\nstops: []\nrealtime: []",
+        )
+        .unwrap();
+        config.stops.push(stop());
+
+        let server =
+            ApiServer::new(Rc::new(config), args::app().get_matches_from(["trs"])).unwrap();
+        let response = server
+            .handle(Request::get(uri).body(Body::empty()).unwrap())
+            .await;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    /// Number of departures of the stop in JSON response.
+    fn departures_count(stop: &serde_json::Value) -> usize {
+        stop["departures"].as_array().unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn stops() {
+        let (status, body) = get("/stops").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["id"], "S5");
+        assert_eq!(body[0]["name"], "Skloněná");
+    }
+
+    #[tokio::test]
+    async fn departures() {
+        let (status, body) = get("/departures?stop=Sklon%C4%9Bn%C3%A1&limit=2").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["stops"][0]["id"], "S5");
+        assert_eq!(departures_count(&body["stops"][0]), 2);
+    }
+
+    #[tokio::test]
+    async fn departures_of_unknown_stop() {
+        let (status, body) = get("/departures?stop=Palmovka").await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body["error"].as_str().unwrap().contains("Palmovka"));
+    }
+
+    #[tokio::test]
+    async fn departures_with_invalid_limit() {
+        let (status, body) = get("/departures?limit=many").await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "Invalid limit \"many\".");
+    }
+
+    #[tokio::test]
+    async fn stop_departures() {
+        let (status, body) = get("/departures/S5?limit=1").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["id"], "S5");
+        assert_eq!(departures_count(&body), 1);
+    }
+
+    #[tokio::test]
+    async fn stop_departures_limit_clamped() {
+        let (status, body) = get("/departures/S5?limit=100000").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(departures_count(&body), MAX_LIMIT);
+    }
+
+    #[tokio::test]
+    async fn stop_departures_errors() {
+        assert_eq!(get("/departures/S6").await.0, StatusCode::NOT_FOUND);
+        assert_eq!(
+            get("/departures/S5?limit=-1").await.0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(get("/arrivals").await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn address_in_use() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let error = Server::try_bind(&listener.local_addr().unwrap())
+            .map(|_| ())
+            .map_err(server_error)
+            .unwrap_err();
+
+        assert!(matches!(error, Error::Service(_)));
+        assert_eq!(error.exit_code(), 8);
+    }
+}
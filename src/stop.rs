@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use derivative::Derivative;
+use gtfs_structures::Gtfs;
+use serde::{Deserialize, Serialize};
+
+use crate::db::{self, Database};
+use crate::error::Error;
+
+/// Stop with its database of departures (records of all trips serving
+/// the stop).
+#[derive(Serialize, Deserialize, Derivative)]
+#[derivative(Debug)]
+pub struct Stop {
+    pub id: String,
+    pub name: String,
+    /// Name of the last stop of trips serving the stop (direction).
+    pub terminating_stop: String,
+    pub wheelchair_boarding: Option<bool>,
+    #[derivative(Debug = "ignore")]
+    pub database: Database,
+}
+
+impl Stop {
+    /// Builds the stop with its database.
+    pub fn new(
+        gtfs: &Gtfs,
+        stop: Arc<gtfs_structures::Stop>,
+        terminating_stop: &gtfs_structures::Stop,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            id: stop.id.clone(),
+            name: stop.name.clone(),
+            terminating_stop: terminating_stop.name.clone(),
+            wheelchair_boarding: db::availability(stop.wheelchair_boarding),
+            database: Database::from(gtfs, stop)?,
+        })
+    }
+}
+
+/// Origin-destination pair with direct trips between the stops.
+#[derive(Serialize, Deserialize, Derivative)]
+#[derivative(Debug)]
+pub struct Connection {
+    pub from: String,
+    pub to: String,
+    #[derivative(Debug = "ignore")]
+    pub database: Database,
+}
+
+impl Connection {
+    /// Builds the connection with its database.
+    pub fn new(
+        gtfs: &Gtfs,
+        from: Arc<gtfs_structures::Stop>,
+        to: Arc<gtfs_structures::Stop>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            from: from.name.clone(),
+            to: to.name.clone(),
+            database: Database::from_connection(gtfs, from, to)?,
+        })
+    }
+}
@@ -1,103 +1,291 @@
-use chrono::{Datelike, Local, NaiveTime, Weekday};
-use std::rc::Rc;
+use chrono::{Duration, Local, NaiveDate};
+use gtfs_structures::Gtfs;
 
-use crate::config::Config;
-use crate::config::Stop;
-use crate::db::Record;
+use crate::db::{Database, Record};
+use crate::error::Error;
+use crate::realtime::ServiceAlert;
+use crate::stop::{Connection, Stop};
 
+/// Stop with its upcoming departures.
 #[derive(Debug)]
 pub struct Departure<'a> {
     pub stop: &'a Stop,
     pub departures: Vec<Record>,
+    /// Service alerts affecting the stop or its departures.
+    pub alerts: Vec<ServiceAlert>,
+    /// Realtime info couldn't be fetched (scheduled times are shown).
+    pub realtime_unavailable: bool,
 }
 
-pub struct Timetables {
-    config: Rc<Config>,
+/// Connection with its upcoming direct trips.
+#[derive(Debug)]
+pub struct ConnectionDeparture<'a> {
+    pub connection: &'a Connection,
+    pub departures: Vec<Record>,
+    /// Service alerts affecting the trips.
+    pub alerts: Vec<ServiceAlert>,
+    /// Realtime info couldn't be fetched (scheduled times are shown).
+    pub realtime_unavailable: bool,
+}
+
+/// One stop of a trip with its times.
+#[derive(Debug)]
+pub struct TripStop {
+    pub name: String,
+    pub arrival_time: Option<u32>,
+    pub departure_time: Option<u32>,
+}
+
+/// Trip with its stops (stop times).
+#[derive(Debug)]
+pub struct TripDetail {
+    pub route: String,
+    pub headsign: Option<String>,
+    pub stops: Vec<TripStop>,
+}
+
+/// Options for departures lookup (limit, look-ahead and filters).
+#[derive(Debug, Clone)]
+pub struct TimetablesConfig {
+    /// Number of departures from each stop (or connection).
+    pub limit: usize,
+    /// Number of days to look ahead when there are not enough departures
+    /// today.
+    pub horizon: i64,
+    /// Wheelchair accessible departures (and stops) only.
+    pub wheelchair: bool,
+    /// Departures allowing bikes only.
+    pub bikes: bool,
+    /// Minutes to look back for departures which may still come thanks
+    /// to a delay (to be checked by realtime info).
+    pub lookback: i64,
 }
 
-impl<'a> Timetables {
-    pub async fn new(config: Rc<Config>) -> Self {
-        Timetables { config }
+impl Default for TimetablesConfig {
+    fn default() -> Self {
+        Self {
+            limit: 3,
+            horizon: 7,
+            wheelchair: false,
+            bikes: false,
+            lookback: 0,
+        }
     }
+}
 
-    pub fn get_departures(&self) -> Vec<Departure> {
-        let mut departures = vec![];
+/// Looks up departures in stop (and connection) databases.
+pub struct Timetables {
+    timetables_config: TimetablesConfig,
+}
 
-        for stop in self.config.stops.iter() {
-            departures.push(Departure {
+impl Timetables {
+    pub fn new(timetables_config: TimetablesConfig) -> Self {
+        Timetables { timetables_config }
+    }
+
+    /// Collects upcoming departures from the given stops.
+    pub fn get_stop_departures<'b>(&self, stops: &'b [Stop]) -> Vec<Departure<'b>> {
+        stops
+            .iter()
+            .map(|stop| Departure {
                 stop,
                 departures: self.get_next_departures(stop),
-            });
-        }
+                alerts: vec![],
+                realtime_unavailable: false,
+            })
+            .collect()
+    }
 
-        departures
+    /// Collects upcoming direct trips of the given connections.
+    pub fn get_connections<'b>(
+        &self,
+        connections: &'b [Connection],
+    ) -> Vec<ConnectionDeparture<'b>> {
+        connections
+            .iter()
+            .map(|connection| ConnectionDeparture {
+                connection,
+                departures: self.get_next_records(&connection.database),
+                alerts: vec![],
+                realtime_unavailable: false,
+            })
+            .collect()
     }
 
+    /// Collects upcoming departures from the stop (with service date set).
     // TODO: async
-    fn get_next_departures(&self, stop: &'a Stop) -> Vec<Record> {
-        let now = Local::now();
-        let date = now.date().naive_utc();
+    pub fn get_next_departures(&self, stop: &Stop) -> Vec<Record> {
+        // Wheelchair users can't board on stops without barrier-free access.
+        if self.timetables_config.wheelchair && stop.wheelchair_boarding == Some(false) {
+            return vec![];
+        }
+
+        self.get_next_records(&stop.database)
+    }
+
+    /// Collects upcoming records from the database. When there are less than
+    /// `limit` records left today following service days are searched
+    /// too (up to `horizon` days ahead). Records departed within `lookback`
+    /// minutes are included too.
+    pub fn get_next_records(&self, database: &Database) -> Vec<Record> {
+        let now = Local::now().naive_local();
+        let since = now - Duration::minutes(self.timetables_config.lookback);
 
         // Set a specific date & time - for debug purposes only!
         // let now = NaiveDate::from_ymd(2020, 12, 7).and_hms(16, 0, 0);
-        // let date = now.date();
 
-        // println!("{}", &now);
+        let mut departures = vec![];
+
+        // Start with yesterday since its trips can run after midnight
+        // (stop times over 24:00:00).
+        for offset in -1..=self.timetables_config.horizon {
+            let date = match now.date().checked_add_signed(Duration::days(offset)) {
+                Some(date) => date,
+                None => break,
+            };
 
-        let mut filtered_and_sorted = stop
+            departures.extend(
+                database
+                    .records
+                    .iter()
+                    // Filter for date and week day.
+                    .filter(|r| r.calendar.runs_on(date))
+                    .filter(|r| self.is_accessible(r))
+                    .map(|r| Record {
+                        service_date: Some(date),
+                        ..r.clone()
+                    })
+                    // Filter for time.
+                    .filter(|r| r.departure().is_some_and(|d| d >= since)),
+            );
+
+            // Only records not departed yet count towards the limit.
+            let upcoming = departures
+                .iter()
+                .filter(|r| r.departure().is_some_and(|d| d >= now))
+                .count();
+
+            if 0 <= offset && self.timetables_config.limit <= upcoming {
+                break;
+            }
+        }
+
+        // Sort by departure (stop time of the service day).
+        departures.sort_by_key(|r| r.departure());
+
+        departures
+    }
+
+    /// Collects all departures from the stop on the given day sorted
+    /// by stop time.
+    pub fn get_day_timetable(&self, stop: &Stop, date: NaiveDate) -> Vec<Record> {
+        let mut departures = stop
             .database
             .records
             .iter()
-            // Filter for date.
-            .filter(|r| {
-                if r.calendar.start_date <= date && r.calendar.end_date >= date {
-                    return true;
-                }
-
-                false
+            .filter(|r| r.calendar.runs_on(date))
+            .filter(|r| self.is_accessible(r))
+            .map(|r| Record {
+                service_date: Some(date),
+                ..r.clone()
             })
-            .filter(|r| {
-                if r.stop_time.is_some() {
-                    let timestamp = r.stop_time.unwrap();
+            .collect::<Vec<Record>>();
 
-                    if timestamp > 60 * 60 * 24 {
-                        // timestamp -= 60 * 60 * 24;
-                        return true;
-                    }
+        departures.sort_by_key(|r| r.stop_time);
 
-                    return NaiveTime::from_num_seconds_from_midnight(timestamp, 0).ge(&now.time());
-                }
+        departures
+    }
 
-                false
-            })
-            // Filter for week day.
-            .filter(|r| match now.weekday() {
-                Weekday::Mon => r.calendar.monday,
-                Weekday::Tue => r.calendar.tuesday,
-                Weekday::Wed => r.calendar.wednesday,
-                Weekday::Thu => r.calendar.thursday,
-                Weekday::Fri => r.calendar.friday,
-                Weekday::Sat => r.calendar.saturday,
-                Weekday::Sun => r.calendar.sunday,
-            })
+    /// Returns all records of the stop (regardless of the service day)
+    /// passing accessibility filters sorted by stop time.
+    pub fn get_stop_records(&self, stop: &Stop) -> Vec<Record> {
+        let mut records = stop
+            .database
+            .records
+            .iter()
+            .filter(|r| self.is_accessible(r))
             .cloned()
             .collect::<Vec<Record>>();
 
-        // Sort by stop time (arrival time).
-        filtered_and_sorted.sort_by_key(|r| r.stop_time);
+        records.sort_by_key(|r| r.stop_time);
+
+        records
+    }
+
+    /// Looks up the trip in GTFS data and returns its stops. When stop ID
+    /// is given only stops from that stop onward are returned.
+    pub fn get_trip(
+        gtfs: &Gtfs,
+        trip_id: &str,
+        stop_id: Option<&str>,
+    ) -> Result<TripDetail, Error> {
+        let trip = gtfs
+            .trips
+            .get(trip_id)
+            .ok_or_else(|| Error::Input(format!("Trip \"{}\" doesn't exist.", trip_id)))?;
+        let route = gtfs.get_route(&trip.route_id)?;
+
+        // Skip stops before the given stop (if any).
+        let start = match stop_id {
+            Some(id) => trip
+                .stop_times
+                .iter()
+                .position(|t| t.stop.id == id)
+                .ok_or_else(|| {
+                    Error::Input(format!("Stop \"{}\" is not on trip \"{}\".", id, trip_id))
+                })?,
+            None => 0,
+        };
+
+        Ok(TripDetail {
+            route: route.short_name.clone(),
+            headsign: trip.trip_headsign.clone(),
+            stops: trip.stop_times[start..]
+                .iter()
+                .map(|t| TripStop {
+                    name: t.stop.name.clone(),
+                    arrival_time: t.arrival_time,
+                    departure_time: t.departure_time,
+                })
+                .collect(),
+        })
+    }
 
-        filtered_and_sorted
+    /// Checks the record against accessibility filters.
+    fn is_accessible(&self, record: &Record) -> bool {
+        (!self.timetables_config.wheelchair || record.wheelchair_accessible == Some(true))
+            && (!self.timetables_config.bikes || record.bikes_allowed == Some(true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::gtfs;
+
+    #[test]
+    fn trip_from_stop() {
+        let gtfs = gtfs("trip-from-stop");
+
+        let trip = Timetables::get_trip(&gtfs, "t", None).unwrap();
+        assert_eq!(trip.stops.len(), 2);
+
+        let trip = Timetables::get_trip(&gtfs, "t", Some("g")).unwrap();
+        let names: Vec<_> = trip.stops.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["Gamma"]);
+    }
 
-        // for record in filtered_and_sorted.iter() {
-        //     if record.stop_time.is_some() {
-        //         println!(
-        //             "{}",
-        //             NaiveDateTime::from_timestamp(record.stop_time.unwrap().into(), 0)
-        //                 .format("%H:%M")
-        //         );
-        //     }
-        // }
+    #[test]
+    fn trip_not_found() {
+        let gtfs = gtfs("trip-not-found");
 
-        // println!("times: {}", filtered_and_sorted.len());
+        assert!(matches!(
+            Timetables::get_trip(&gtfs, "x", None),
+            Err(Error::Input(_))
+        ));
+        assert!(matches!(
+            Timetables::get_trip(&gtfs, "t", Some("b")),
+            Err(Error::Input(msg)) if msg.contains("not on trip")
+        ));
     }
 }
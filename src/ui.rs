@@ -1,22 +1,34 @@
+use std::collections::BTreeSet;
 use std::fmt::{self, Display};
 use std::io::{self, prelude::*, BufRead};
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
 
-use chrono::{Local, NaiveTime};
+use chrono::{Local, NaiveDate, NaiveDateTime};
 use clap::ArgMatches;
 use gtfs_structures::{Gtfs, Stop};
+use itertools::Itertools;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use spinners::{Spinner, Spinners};
 
-use crate::db::DataFile;
-#[cfg(feature = "prague")]
-use crate::db::Record;
-#[cfg(feature = "prague")]
-use crate::features::prague;
-use crate::timetables::Departure;
+use trs::db::{CustomCalendar, DataFile, Record};
+use trs::error::Error;
+use trs::planner::{Itinerary, Leg};
+use trs::realtime::{RealtimeProvider, ServiceAlert, Severity, TripStatus, Vehicle};
+use trs::stop;
+use trs::timetables::{ConnectionDeparture, Departure, TripDetail};
+
+use crate::args;
+
+/// Escapes text to be safely embedded in HTML.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
 
 pub struct WizardOutput {
     pub gtfs: Gtfs,
@@ -37,6 +49,20 @@ impl Display for FoundStop {
     }
 }
 
+/// Origin and destination stops of user's connection.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FoundConnection {
+    pub from: FoundStop,
+    pub to: FoundStop,
+}
+
+/// Implementing Display trait so the connection can be printed out.
+impl Display for FoundConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} => {}", self.from.stop, self.to.stop)
+    }
+}
+
 /// Wizard for user that ask a few questions.
 /// The result is used by Config struct.
 pub struct Wizard<'a> {
@@ -55,7 +81,7 @@ impl<'a> Wizard<'a> {
     }
 
     /// Kick offs wizard which returns output containing GTFS data and user's chosen stops.
-    pub async fn run_wizard(&mut self) -> Result<WizardOutput, Box<dyn std::error::Error>> {
+    pub async fn run_wizard(&mut self) -> Result<WizardOutput, Error> {
         let gtfs = self.determine_retrieve_and_parse_data_file().await?;
         let stops = self.read_stop_names(&gtfs)?;
 
@@ -65,9 +91,7 @@ impl<'a> Wizard<'a> {
     /// Downloads or copies (depends on the origin location) the datafile
     /// to project config location (see Config.path) and parses it's content
     /// which is then returned.
-    async fn determine_retrieve_and_parse_data_file(
-        &mut self,
-    ) -> Result<Gtfs, Box<dyn std::error::Error>> {
+    async fn determine_retrieve_and_parse_data_file(&mut self) -> Result<Gtfs, Error> {
         // Determine (read) data file path/URL.
         println!("Enter data file path/URL: ");
         let mut data_file = String::new();
@@ -91,7 +115,7 @@ impl<'a> Wizard<'a> {
 
         // Spinner - start.
         let mut sp = Spinner::new(Spinners::Line, "parsing...".into());
-        io::stdout().flush().unwrap();
+        io::stdout().flush()?;
 
         // Parse.
         let gtfs = df.parse()?;
@@ -105,10 +129,7 @@ impl<'a> Wizard<'a> {
 
     /// Triggers the loop for reading stop names. User can
     /// enter as many stops as he likes.
-    pub fn read_stop_names(
-        &self,
-        gtfs: &'a Gtfs,
-    ) -> Result<Vec<FoundStop>, Box<dyn std::error::Error>> {
+    pub fn read_stop_names(&self, gtfs: &'a Gtfs) -> Result<Vec<FoundStop>, Error> {
         let mut chosen_stops = vec![];
 
         loop {
@@ -130,8 +151,18 @@ impl<'a> Wizard<'a> {
         Ok(chosen_stops)
     }
 
+    /// Reads origin and destination stop of a connection.
+    pub fn read_connection(&self, gtfs: &'a Gtfs) -> Result<FoundConnection, Error> {
+        Ui::info("Origin stop:");
+        let from = self.read_stop_name(gtfs)?;
+        Ui::info("Destination stop:");
+        let to = self.read_stop_name(gtfs)?;
+
+        Ok(FoundConnection { from, to })
+    }
+
     /// Tries to collect one stop based on user input.
-    fn read_stop_name(&self, gtfs: &'a Gtfs) -> Result<FoundStop, Box<dyn std::error::Error>> {
+    fn read_stop_name(&self, gtfs: &'a Gtfs) -> Result<FoundStop, Error> {
         loop {
             let mut found_stops = self.seek_stops(gtfs)?;
 
@@ -147,7 +178,10 @@ impl<'a> Wizard<'a> {
                 .map(|item| FoundStop {
                     id: item.0.clone(),
                     stop: item.1.clone(),
-                    terminating_stop: self.get_terminating_trip_stop_for_stop(gtfs, item.1.clone()),
+                    terminating_stop: Self::get_terminating_trip_stop_for_stop(
+                        gtfs,
+                        item.1.clone(),
+                    ),
                 })
                 .collect();
 
@@ -196,10 +230,7 @@ impl<'a> Wizard<'a> {
     /// Asks user for input and then finds similar stops in datafile.
     /// All similar stops are then returned.
     /// If no similar stops are found user is asked for the input again.
-    fn seek_stops(
-        &self,
-        gtfs: &'a Gtfs,
-    ) -> Result<Vec<(String, Arc<Stop>)>, Box<dyn std::error::Error>> {
+    fn seek_stops(&self, gtfs: &'a Gtfs) -> Result<Vec<(String, Arc<Stop>)>, Error> {
         let mut found_stops: Vec<(String, Arc<Stop>)>;
 
         loop {
@@ -229,158 +260,1115 @@ impl<'a> Wizard<'a> {
 
     /// Seeks last stop (terminating station) for the given stop (based on
     /// associated trip and stop times.
-    fn get_terminating_trip_stop_for_stop(&self, gtfs: &'a Gtfs, stop: Arc<Stop>) -> Arc<Stop> {
+    pub fn get_terminating_trip_stop_for_stop(gtfs: &Gtfs, stop: Arc<Stop>) -> Arc<Stop> {
         let mut found_stop: Option<Arc<Stop>> = None;
 
         // Closes thing to stops we have are trips.
         for (_, trip) in gtfs.trips.iter() {
             for time in trip.stop_times.iter() {
                 if time.stop.id == stop.id {
-                    found_stop = Some(
-                        trip.stop_times
-                            .last()
-                            .expect("Trip ha no stop - that's very weird!")
-                            .stop
-                            .clone(),
-                    );
+                    found_stop = trip.stop_times.last().map(|t| t.stop.clone());
                 }
             }
         }
 
-        if found_stop.is_none() {
-            return stop;
-        }
-
-        found_stop.unwrap()
+        found_stop.unwrap_or(stop)
     }
 }
 
+/// Departures sooner than this (minutes) are highlighted in status bars.
+const SOON_MINUTES: i64 = 5;
+/// How many times more departures than the limit are enriched with realtime
+/// info before cutting them to the limit.
+const REALTIME_WINDOW: usize = 2;
+
+/// Stylesheet of the HTML departure board.
+const BOARD_STYLE: &str = "
+body { margin: 0; padding: 1em; background: #111; color: #eee; font-family: sans-serif; font-size: 1.5em; }
+h2 { margin: 1em 0 0.3em; font-size: 1.2em; color: #fc0; }
+table { width: 100%; border-collapse: collapse; }
+td { padding: 0.25em 0.5em; border-bottom: 1px solid #333; white-space: nowrap; }
+td.headsign { width: 100%; white-space: normal; }
+td.time, td.in { text-align: right; font-variant-numeric: tabular-nums; }
+.route { display: inline-block; min-width: 2.5em; padding: 0.1em 0.4em; border-radius: 0.3em; background: #eee; color: #111; font-weight: bold; text-align: center; }
+tr.soon td.in { color: #f55; font-weight: bold; }
+tr.canceled td { text-decoration: line-through; color: #777; }
+footer { margin-top: 1em; font-size: 0.6em; color: #777; }
+";
+
+/// Script of the HTML departure board counting down minutes till departures.
+const BOARD_SCRIPT: &str = "
+function update() {
+    const now = Date.now();
+    document.querySelectorAll('tr[data-departure]').forEach(function (row) {
+        const minutes = Math.floor((new Date(row.dataset.departure) - now) / 60000);
+        row.hidden = minutes < 0;
+        row.classList.toggle('soon', minutes <= 5);
+        row.querySelector('td.in').textContent = minutes + ' min';
+    });
+}
+update();
+setInterval(update, 15000);
+";
+
+/// Output formats of the departures listing.
+pub enum OutputFormat {
+    Default,
+    Json,
+    Waybar,
+    Line,
+}
+
 pub struct UiConfig {
     limit: usize,
+    format: OutputFormat,
+    realtime: bool,
+}
+
+/// Waybar custom module output.
+#[derive(Serialize)]
+struct WaybarOutput {
+    text: String,
+    tooltip: String,
+    class: &'static str,
+}
+
+/// Whole JSON output.
+#[derive(Serialize)]
+pub struct JsonOutput<'a> {
+    stops: Vec<JsonStop<'a>>,
+    connections: Vec<JsonConnection<'a>>,
+}
+
+/// One connection with its departures as serialized by JSON output.
+#[derive(Serialize)]
+pub struct JsonConnection<'a> {
+    from: &'a str,
+    to: &'a str,
+    departures: Vec<JsonDeparture<'a>>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    alerts: &'a [ServiceAlert],
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    realtime_unavailable: bool,
+}
+
+/// One stop with its departures as serialized by JSON output.
+#[derive(Serialize)]
+pub struct JsonStop<'a> {
+    id: &'a str,
+    name: &'a str,
+    terminating_stop: &'a str,
+    wheelchair_boarding: Option<bool>,
+    departures: Vec<JsonDeparture<'a>>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    alerts: &'a [ServiceAlert],
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    realtime_unavailable: bool,
+}
+
+/// One departure as serialized by JSON output.
+#[derive(Serialize)]
+pub struct JsonDeparture<'a> {
+    route: &'a str,
+    headsign: Option<&'a str>,
+    date: NaiveDate,
+    time: String,
+    label: String,
+    trip_id: &'a str,
+    in_minutes: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arrival_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ride_minutes: Option<i64>,
+    wheelchair_accessible: Option<bool>,
+    bikes_allowed: Option<bool>,
+    icons: String,
+    /// Realtime info (if any).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    predicted_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delay_minutes: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<TripStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    air_conditioned: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    platform: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vehicle: Option<&'a Vehicle>,
 }
 
 pub struct Ui {
     config: Rc<UiConfig>,
+    realtime: Vec<Box<dyn RealtimeProvider>>,
 }
 
 impl Ui {
-    pub fn new(args: ArgMatches) -> Self {
-        Self {
-            config: Rc::new(Ui::process_args(args)),
+    pub fn new(args: ArgMatches) -> Result<Self, Error> {
+        Ok(Self {
+            config: Rc::new(Ui::process_args(args)?),
+            realtime: vec![],
+        })
+    }
+
+    /// Sets providers departures are enriched with realtime info by
+    /// (unless realtime is turned off by `--no-realtime`).
+    pub fn with_realtime(mut self, providers: Vec<Box<dyn RealtimeProvider>>) -> Self {
+        if self.config.realtime {
+            self.realtime = providers;
         }
+
+        self
     }
 
-    pub fn process_args(args: ArgMatches) -> UiConfig {
+    pub fn process_args(args: ArgMatches) -> Result<UiConfig, Error> {
         // -l argument
-        let limit = args
-            .get_one::<String>("limit")
-            .unwrap()
-            .parse::<usize>()
-            .unwrap();
-
-        UiConfig { limit }
+        let limit = args::value(&args, "limit")?;
+
+        // -f argument
+        let format = match args.get_one::<String>("format").map(String::as_str) {
+            Some("json") => OutputFormat::Json,
+            Some("waybar") => OutputFormat::Waybar,
+            Some("line") => OutputFormat::Line,
+            _ => OutputFormat::Default,
+        };
+
+        // --no-realtime argument
+        let realtime = !args.is_present("no-realtime");
+
+        Ok(UiConfig {
+            limit,
+            format,
+            realtime,
+        })
     }
 
-    pub async fn output(&self, departures: Vec<Departure<'_>>) {
+    pub async fn output(
+        &self,
+        departures: &mut [Departure<'_>],
+        connections: &mut [ConnectionDeparture<'_>],
+    ) -> Result<(), Error> {
         // dbg!(&departures);
 
-        self.print_default(departures).await
+        // Status bars show the first stop of the config only so the other
+        // stops (and connections) are not enriched at all.
+        let (departures, connections) = match self.config.format {
+            OutputFormat::Waybar | OutputFormat::Line => {
+                let shown = departures.len().min(1);
+                (&mut departures[..shown], &mut connections[..0])
+            }
+            _ => (departures, connections),
+        };
+
+        self.prepare(departures, connections, self.config.limit)
+            .await?;
+
+        match self.config.format {
+            OutputFormat::Default => self.print_default(departures, connections),
+            OutputFormat::Json => self.print_json(departures, connections),
+            OutputFormat::Waybar => self.print_waybar(departures),
+            OutputFormat::Line => self.print_line(departures),
+        }
+
+        Ok(())
+    }
+
+    /// Sorts departures by stop name, cuts them (and connections) to the limit
+    /// and enriches them with realtime info (if any provider is set). When
+    /// realtime data are not available, scheduled times are kept and marked
+    /// as such.
+    pub async fn prepare(
+        &self,
+        departures: &mut [Departure<'_>],
+        connections: &mut [ConnectionDeparture<'_>],
+        limit: usize,
+    ) -> Result<(), Error> {
+        // Sort  by stop name.
+        departures.sort_by(|a, b| a.stop.name.cmp(&b.stop.name));
+
+        // Realtime changes of the schedule add and remove departures so
+        // they are applied before cutting to the limit.
+        let outcome = self.update_schedule(departures, connections).await;
+
+        let mut groups = departures
+            .iter_mut()
+            .map(|d| &mut d.departures)
+            .chain(connections.iter_mut().map(|c| &mut c.departures))
+            .collect::<Vec<&mut Vec<Record>>>();
+
+        // Delays can reorder departures so a wider window (with departures
+        // scheduled in the past which may still come) is enriched before
+        // cutting to the limit by the expected departure.
+        let now = Local::now().naive_local();
+
+        for records in groups.iter_mut() {
+            records.sort_by_key(Record::departure);
+
+            let departed = records
+                .iter()
+                .take_while(|r| r.departure().is_some_and(|d| d < now))
+                .count();
+            records.truncate(
+                limit
+                    .saturating_mul(REALTIME_WINDOW)
+                    .saturating_add(departed),
+            );
+        }
+
+        let outcome = outcome.and(self.spice_up(&mut groups).await);
+
+        for records in groups.iter_mut() {
+            records.retain(|r| r.expected_departure().is_some_and(|d| d >= now));
+            records.sort_by_key(Record::expected_departure);
+            records.truncate(limit);
+        }
+        let outcome = outcome.and(self.attach_alerts(departures, connections).await);
+
+        // Any failure of the providers (network, API key, client setup...)
+        // falls back to scheduled times.
+        if let Err(e) = outcome {
+            eprintln!("Warning: {}", e);
+
+            for departure in departures.iter_mut() {
+                departure.realtime_unavailable = true;
+            }
+            for connection in connections.iter_mut() {
+                connection.realtime_unavailable = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies realtime changes of the schedule (cancelled, skipped or
+    /// added trips) of all providers. Failing provider doesn't stop the
+    /// others - the first error is returned afterwards.
+    pub async fn update_schedule(
+        &self,
+        departures: &mut [Departure<'_>],
+        connections: &mut [ConnectionDeparture<'_>],
+    ) -> Result<(), Error> {
+        let mut outcome = Ok(());
+
+        for provider in self.realtime.iter() {
+            outcome = outcome.and(provider.update_schedule(departures, connections).await);
+        }
+
+        outcome
+    }
+
+    /// Attaches active service alerts (of all providers) to the stops and
+    /// connections they affect.
+    async fn attach_alerts(
+        &self,
+        departures: &mut [Departure<'_>],
+        connections: &mut [ConnectionDeparture<'_>],
+    ) -> Result<(), Error> {
+        let mut alerts = vec![];
+        let mut outcome = Ok(());
+
+        for provider in self.realtime.iter() {
+            match provider.alerts().await {
+                Ok(provider_alerts) => alerts.extend(provider_alerts),
+                Err(e) => outcome = outcome.and(Err(e)),
+            }
+        }
+
+        for departure in departures.iter_mut() {
+            departure.alerts = alerts
+                .iter()
+                .filter(|a| a.affects(Some(&departure.stop.id), &departure.departures))
+                .cloned()
+                .collect();
+        }
+
+        for connection in connections.iter_mut() {
+            connection.alerts = alerts
+                .iter()
+                .filter(|a| a.affects(None, &connection.departures))
+                .cloned()
+                .collect();
+        }
+
+        outcome
+    }
+
+    /// Enriches groups of records (of each stop or connection) with
+    /// realtime info of all providers - all groups at once so the same
+    /// route is looked up only once.
+    pub async fn spice_up(&self, groups: &mut [&mut Vec<Record>]) -> Result<(), Error> {
+        let mut records: Vec<&mut Record> = groups.iter_mut().flat_map(|g| g.iter_mut()).collect();
+
+        let mut outcome = Ok(());
+
+        for provider in self.realtime.iter() {
+            outcome = outcome.and(provider.enrich(&mut records).await);
+        }
+
+        outcome
     }
 
     /// Prints departures in default format:
     ///
-    /// Novovysočanská -> Sídliště Čakovice
-    /// -----------------------------------
-    /// 109 - 15:33 (+10 min)
-    /// 109 - 15:45 (+22 min)
-    /// 109 - 15:57 (+34 min)
+    /// Novovysočanská -> Sídliště Čakovice ♿
+    /// -------------------------------------
+    /// 109 → Sídliště Čakovice 15:33 (in 10 min) ♿
+    /// 109 → Sídliště Čakovice 15:45 (in 22 min) ♿🚲
+    /// 109 → Sídliště Čakovice 15:57 (in 34 min)
     ///
-    async fn print_default(&self, mut departures: Vec<Departure<'_>>) {
-        // Sort  by stop name.
-        departures.sort_by(|a, b| a.stop.name.partial_cmp(&b.stop.name).unwrap());
+    /// Connections are printed afterwards:
+    ///
+    /// Novovysočanská => Palmovka
+    /// --------------------------
+    /// 109 → Sídliště Čakovice 15:33 - 15:41 (8 min ride, in 10 min)
+    ///
+    fn print_default(&self, departures: &[Departure<'_>], connections: &[ConnectionDeparture<'_>]) {
+        for departure in departures.iter() {
+            // Heading.
+            let heading = Self::format_heading(departure);
+            println!();
+            println!("{}", heading);
+            println!("{}", "-".repeat(heading.chars().count()));
 
-        for departure in departures.iter_mut() {
+            Self::print_alerts(&departure.alerts);
+
+            // Timetable.
+            for departure_record in departure.departures.iter() {
+                if let Some(line) = self.format_record(departure_record) {
+                    println!("{}", line);
+                }
+            }
+        }
+
+        for connection in connections.iter() {
             // Heading.
-            let heading = format!(
-                "{} -> {}",
-                departure.stop.name, &departure.stop.terminating_stop
-            );
+            let heading = Self::format_connection_heading(connection);
             println!();
             println!("{}", heading);
             println!("{}", "-".repeat(heading.chars().count()));
 
-            let now = Local::now();
+            Self::print_alerts(&connection.alerts);
 
-            // Check if limit is greater than actual departures count.
-            let limit = if departure.departures.len() < self.config.limit {
-                departure.departures.len()
-            } else {
-                self.config.limit
+            for record in connection.departures.iter() {
+                if let (Some((departure, in_minutes)), Some(arrival)) =
+                    (Self::departure_time(record), record.arrival())
+                {
+                    println!(
+                        "{} {} - {} ({} min ride, in {} min){}{}",
+                        Self::format_route(record),
+                        Self::format_departure(departure),
+                        arrival.format("%H:%M"),
+                        (arrival - departure).num_minutes(),
+                        in_minutes,
+                        Self::format_accessibility(record),
+                        Self::format_realtime(record),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Prints service alerts with their severity and active period like:
+    ///
+    /// ⚠ Skloněná stop is closed (from 18.10. 05:00 until 20.10. 23:59)
+    ///   Buses stop at the temporary stop in Kolbenova street.
+    fn print_alerts(alerts: &[ServiceAlert]) {
+        for alert in alerts {
+            let icon = match alert.severity {
+                Severity::Severe => "‼",
+                Severity::Info => "ℹ",
+                _ => "⚠",
+            };
+            let format = |time: NaiveDateTime| time.format("%-d.%-m. %H:%M").to_string();
+            let period = match (alert.active_from, alert.active_until) {
+                (Some(from), Some(until)) => {
+                    format!(" (from {} until {})", format(from), format(until))
+                }
+                (Some(from), None) => format!(" (from {})", format(from)),
+                (None, Some(until)) => format!(" (until {})", format(until)),
+                (None, None) => String::new(),
             };
 
-            #[cfg(feature = "prague")]
-            let deps_slice = &mut departure.departures[..limit];
-            #[cfg(feature = "prague")]
-            prague::spice_up_departures(Rc::clone(&self.config), deps_slice).await;
+            println!("{} {}{}", icon, alert.header, period);
 
-            // Timetable.
-            for departure_record in departure.departures[..limit].iter() {
-                if departure_record.stop_time.is_some() {
-                    // Is the departure time "next day"? - means the departure
-                    // seconds are greater than 86400 seconds.
-                    let mut next_day = false;
-                    let departure = NaiveTime::from_num_seconds_from_midnight(
-                        if 60 * 60 * 24 < departure_record.stop_time.unwrap() {
-                            next_day = true;
-                            departure_record.stop_time.unwrap() - 60 * 60 * 24
-                        } else {
-                            departure_record.stop_time.unwrap()
-                        },
-                        0,
-                    );
+            for line in alert.description.iter().flat_map(|d| d.lines()) {
+                println!("  {}", line);
+            }
+        }
+    }
+
+    /// Prints the next departure from the first stop (of the config) as
+    /// Waybar custom module JSON - text, tooltip (with all the departures)
+    /// and class.
+    fn print_waybar(&self, departures: &[Departure<'_>]) {
+        let departure = departures.first();
+        let next = departure.and_then(|d| d.departures.first());
+
+        let output = WaybarOutput {
+            text: next.and_then(Self::format_compact).unwrap_or_default(),
+            tooltip: departure
+                .map(|d| {
+                    std::iter::once(Self::format_heading(d))
+                        .chain(d.departures.iter().filter_map(|r| self.format_record(r)))
+                        .join("\n")
+                })
+                .unwrap_or_default(),
+            class: match next.and_then(Self::departure_time) {
+                Some((_, in_minutes)) if in_minutes <= SOON_MINUTES => "soon",
+                Some(_) => "departure",
+                None => "none",
+            },
+        };
+
+        println!("{}", serde_json::to_string(&output).unwrap());
+    }
 
-                    #[cfg(not(feature = "prague"))]
-                    let additional = String::new();
-                    #[cfg(feature = "prague")]
-                    let mut additional = String::new();
+    /// Prints the next departure from the first stop (of the config) on one
+    /// line (for status bars like i3blocks or polybar).
+    fn print_line(&self, departures: &[Departure<'_>]) {
+        println!(
+            "{}",
+            departures
+                .first()
+                .and_then(|d| d.departures.first())
+                .and_then(Self::format_compact)
+                .unwrap_or_default()
+        );
+    }
 
-                    #[cfg(feature = "prague")]
-                    self.format_additionals(departure_record, &mut additional);
+    /// Renders departures (and connections) as self-contained HTML page
+    /// for departure boards (kiosk browser, intranet page). The page reloads
+    /// itself every `refresh` seconds and counts down minutes remaining
+    /// till each departure (hiding the departed ones) in between.
+    pub async fn board(
+        &self,
+        departures: &mut [Departure<'_>],
+        connections: &mut [ConnectionDeparture<'_>],
+        refresh: u32,
+    ) -> Result<String, Error> {
+        self.prepare(departures, connections, self.config.limit)
+            .await?;
+
+        let mut sections = String::new();
+
+        for departure in departures.iter() {
+            sections.push_str(&Self::board_section(
+                &Self::format_heading(departure),
+                departure
+                    .departures
+                    .iter()
+                    .filter_map(|r| self.board_row(r)),
+            ));
+        }
 
-                    println!(
-                        "{} - {} (in {} min){}",
-                        departure_record.route,
-                        departure.format("%H:%M"),
-                        {
-                            // In case of "next day" departure we need to add one day.
-                            let mut x = departure - now.time();
-
-                            if next_day {
-                                x = x + chrono::Duration::days(1)
-                            }
-
-                            x.num_minutes()
+        for connection in connections.iter() {
+            sections.push_str(&Self::board_section(
+                &Self::format_connection_heading(connection),
+                connection
+                    .departures
+                    .iter()
+                    .filter_map(|r| self.board_row(r)),
+            ));
+        }
+
+        Ok(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="{}">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Departures</title>
+<style>{}</style>
+</head>
+<body>
+{}<footer>Updated {}</footer>
+<script>{}</script>
+</body>
+</html>
+"#,
+            refresh,
+            BOARD_STYLE,
+            sections,
+            Local::now().format("%H:%M"),
+            BOARD_SCRIPT
+        ))
+    }
+
+    /// Renders board section (stop or connection) with its departures.
+    fn board_section(heading: &str, rows: impl Iterator<Item = String>) -> String {
+        format!(
+            "<section>\n<h2>{}</h2>\n<table>\n{}</table>\n</section>\n",
+            escape_html(heading),
+            rows.collect::<String>()
+        )
+    }
+
+    /// Renders one departure as board table row (with the arrival for
+    /// connections). Route is styled with its colors (if known).
+    fn board_row(&self, record: &Record) -> Option<String> {
+        let (departure, in_minutes) = Self::departure_time(record)?;
+
+        let style = match (&record.route_color, &record.route_text_color) {
+            (Some(color), Some(text_color)) => {
+                format!(r#" style="background:#{};color:#{}""#, color, text_color)
+            }
+            _ => String::new(),
+        };
+        let time = match record.arrival() {
+            Some(arrival) => format!(
+                "{} - {}",
+                Self::format_departure(departure),
+                arrival.format("%H:%M")
+            ),
+            None => Self::format_departure(departure),
+        };
+
+        let additional = Self::format_realtime(record);
+        let class = match &record.realtime {
+            Some(realtime) if realtime.is_canceled() => r#" class="canceled""#,
+            _ => "",
+        };
+
+        Some(format!(
+            "<tr{} data-departure=\"{}\"><td><span class=\"route\"{}>{}</span></td>\
+             <td class=\"headsign\">{}</td><td class=\"time\">{}</td>\
+             <td class=\"in\">{} min</td><td class=\"icons\">{}{}</td></tr>\n",
+            class,
+            record
+                .expected_departure()
+                .unwrap_or(departure)
+                .format("%Y-%m-%dT%H:%M:%S"),
+            style,
+            escape_html(&record.route),
+            escape_html(record.headsign().unwrap_or_default()),
+            time,
+            in_minutes,
+            Self::format_accessibility(record).trim(),
+            escape_html(&additional)
+        ))
+    }
+
+    /// Prints departures as JSON object with stops and connections.
+    fn print_json(&self, departures: &[Departure<'_>], connections: &[ConnectionDeparture<'_>]) {
+        let output = Self::json_output(departures, connections);
+
+        println!("{}", serde_json::to_string_pretty(&output).unwrap());
+    }
+
+    /// Converts departures and connections to their JSON representation.
+    pub fn json_output<'a>(
+        departures: &'a [Departure<'_>],
+        connections: &'a [ConnectionDeparture<'_>],
+    ) -> JsonOutput<'a> {
+        JsonOutput {
+            stops: departures.iter().map(Self::json_stop).collect(),
+            connections: connections
+                .iter()
+                .map(|connection| JsonConnection {
+                    from: &connection.connection.from,
+                    to: &connection.connection.to,
+                    departures: connection
+                        .departures
+                        .iter()
+                        .filter_map(Self::json_departure)
+                        .collect(),
+                    alerts: &connection.alerts,
+                    realtime_unavailable: connection.realtime_unavailable,
+                })
+                .collect(),
+        }
+    }
+
+    /// Converts the stop with its departures to JSON representation.
+    pub fn json_stop<'a>(departure: &'a Departure<'_>) -> JsonStop<'a> {
+        JsonStop {
+            id: &departure.stop.id,
+            name: &departure.stop.name,
+            terminating_stop: &departure.stop.terminating_stop,
+            wheelchair_boarding: departure.stop.wheelchair_boarding,
+            departures: departure
+                .departures
+                .iter()
+                .filter_map(Self::json_departure)
+                .collect(),
+            alerts: &departure.alerts,
+            realtime_unavailable: departure.realtime_unavailable,
+        }
+    }
+
+    /// Converts the record to its JSON representation.
+    fn json_departure(record: &Record) -> Option<JsonDeparture<'_>> {
+        let (departure, in_minutes) = Self::departure_time(record)?;
+        let realtime = record.realtime.as_ref();
+
+        Some(JsonDeparture {
+            route: &record.route,
+            headsign: record.headsign(),
+            date: departure.date(),
+            time: departure.format("%H:%M").to_string(),
+            label: Self::format_departure(departure),
+            trip_id: &record.trip_id,
+            in_minutes,
+            arrival_time: record.arrival().map(|a| a.format("%H:%M").to_string()),
+            ride_minutes: record.arrival().map(|a| (a - departure).num_minutes()),
+            wheelchair_accessible: record.wheelchair_accessible,
+            bikes_allowed: record.bikes_allowed,
+            icons: Self::format_accessibility(record).trim().to_string(),
+            predicted_time: realtime
+                .and_then(|r| r.departure)
+                .map(|d| d.format("%H:%M").to_string()),
+            delay_minutes: realtime.and_then(|r| r.delay).map(|d| d.num_minutes()),
+            status: realtime.map(|r| r.status),
+            air_conditioned: realtime.and_then(|r| r.air_conditioned),
+            platform: realtime.and_then(|r| r.platform.as_deref()),
+            vehicle: realtime.and_then(|r| r.vehicle.as_ref()),
+        })
+    }
+
+    /// Prints full day timetable of the stop in the layout of printed
+    /// timetables - hours down the side, minutes across (for each route).
+    /// Trips which don't run every day are marked with a footnote:
+    ///
+    /// Skloněná -> Sídliště Čakovice (Mon 19.10.2026)
+    /// ----------------------------------------------
+    ///
+    /// 136
+    ///  5 | 10a 25  40a 55
+    ///  6 | 10a 25  40a 55
+    ///
+    /// a) runs Mon–Fri
+    ///
+    pub fn print_timetable(&self, stop: &stop::Stop, date: NaiveDate, records: &[Record]) {
+        // Heading.
+        let heading = format!(
+            "{} -> {} ({})",
+            stop.name,
+            stop.terminating_stop,
+            date.format("%a %d.%m.%Y")
+        );
+        println!();
+        println!("{}", heading);
+        println!("{}", "-".repeat(heading.chars().count()));
+
+        let mut footnotes: Vec<String> = vec![];
+        let routes = records
+            .iter()
+            .map(|r| r.route.as_str())
+            .collect::<BTreeSet<&str>>();
+
+        for route in routes {
+            // Minutes grouped by hours.
+            let mut hours: Vec<(u32, Vec<String>)> = vec![];
+
+            for record in records.iter().filter(|r| r.route == route) {
+                let stop_time = match record.stop_time {
+                    Some(stop_time) => stop_time,
+                    None => continue,
+                };
+                let hour = stop_time / 3600;
+                let mut minute = format!("{:02}", stop_time % 3600 / 60);
+
+                // Mark trips running only on some days.
+                if let Some(days) = Self::format_service_days(&record.calendar) {
+                    let index = match footnotes.iter().position(|f| *f == days) {
+                        Some(index) => index,
+                        None => {
+                            footnotes.push(days);
+                            footnotes.len() - 1
+                        }
+                    };
+                    minute.push_str(&Self::footnote_mark(index));
+                }
+
+                match hours.last_mut() {
+                    Some((h, minutes)) if *h == hour => minutes.push(minute),
+                    _ => hours.push((hour, vec![minute])),
+                }
+            }
+
+            // Minutes with the longest footnote mark so far.
+            let width = 2 + Self::footnote_mark(footnotes.len().saturating_sub(1)).len();
+
+            println!();
+            println!("{}", route);
+
+            for (hour, minutes) in hours {
+                // Stop times after midnight belong to the service day too.
+                println!(
+                    "{:>2} | {}",
+                    hour % 24,
+                    minutes
+                        .iter()
+                        .map(|m| format!("{:<width$}", m, width = width))
+                        .join(" ")
+                        .trim_end()
+                );
+            }
+        }
+
+        if !footnotes.is_empty() {
+            println!();
+        }
+
+        for (index, days) in footnotes.iter().enumerate() {
+            println!("{}) runs {}", Self::footnote_mark(index), days);
+        }
+    }
+
+    /// Describes week days of the calendar like "Mon–Fri" or "Sat, Sun".
+    /// Returns None for calendars running every day.
+    fn format_service_days(calendar: &CustomCalendar) -> Option<String> {
+        let days = [
+            (calendar.monday, "Mon"),
+            (calendar.tuesday, "Tue"),
+            (calendar.wednesday, "Wed"),
+            (calendar.thursday, "Thu"),
+            (calendar.friday, "Fri"),
+            (calendar.saturday, "Sat"),
+            (calendar.sunday, "Sun"),
+        ];
+
+        if days.iter().all(|(runs, _)| *runs) {
+            return None;
+        }
+
+        // Collapse consecutive days into ranges.
+        let mut ranges: Vec<&[(bool, &str)]> = vec![];
+        let mut start = None;
+
+        for (i, (runs, _)) in days.iter().enumerate() {
+            match (runs, start) {
+                (true, None) => start = Some(i),
+                (false, Some(s)) => {
+                    ranges.push(&days[s..i]);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(s) = start {
+            ranges.push(&days[s..]);
+        }
+
+        if ranges.is_empty() {
+            return Some("on selected days".to_string());
+        }
+
+        Some(
+            ranges
+                .iter()
+                .map(|range| match range {
+                    [(_, day)] => day.to_string(),
+                    [(_, first), (_, second)] => format!("{}, {}", first, second),
+                    _ => format!("{}–{}", range[0].1, range[range.len() - 1].1),
+                })
+                .join(", "),
+        )
+    }
+
+    /// Returns footnote mark (a, b, ... z, aa, ab...) for the given
+    /// footnote index.
+    fn footnote_mark(index: usize) -> String {
+        let letter = char::from(b'a' + (index % 26) as u8);
+
+        match index / 26 {
+            0 => letter.to_string(),
+            prefix => format!("{}{}", Self::footnote_mark(prefix - 1), letter),
+        }
+    }
+
+    /// Prints stops of the trip with their arrival times:
+    ///
+    /// 136 → Sídliště Čakovice
+    /// -----------------------
+    /// 22:10 Skloněná
+    /// 22:25 Sídliště Čakovice
+    ///
+    pub fn print_trip(&self, trip: &TripDetail, service_date: Option<NaiveDate>) {
+        let heading = match &trip.headsign {
+            Some(headsign) => format!("{} → {}", trip.route, headsign),
+            None => trip.route.clone(),
+        };
+        println!();
+        println!("{}", heading);
+        println!("{}", "-".repeat(heading.chars().count()));
+
+        for stop in trip.stops.iter() {
+            let time = match stop.arrival_time.or(stop.departure_time) {
+                Some(stop_time) => match service_date.and_then(|d| d.and_hms_opt(0, 0, 0)) {
+                    // Stop time is counted from the service day midnight.
+                    Some(midnight) => Self::format_departure(
+                        midnight + chrono::Duration::seconds(stop_time.into()),
+                    ),
+                    None => format!("{:02}:{:02}", stop_time / 3600 % 24, stop_time % 3600 / 60),
+                },
+                None => "--:--".to_string(),
+            };
+
+            println!("{} {}", time, stop.name);
+        }
+    }
+
+    /// Prints planned itineraries:
+    ///
+    /// 22:10 - 22:36 (26 min, 1 change)
+    ///   136 → Palmovka: 22:10 Skloněná - 22:20 Palmovka
+    ///   walk 2 min: Palmovka - Palmovka
+    ///   9 → Náměstí Míru: 22:24 Palmovka - 22:36 Náměstí Míru
+    ///
+    pub fn print_itineraries(&self, date: NaiveDate, itineraries: &[Itinerary]) {
+        if itineraries.is_empty() {
+            Self::info("No itinerary was found.");
+        }
+
+        let time = |seconds: u32| Self::format_planned_time(date, seconds);
+
+        for itinerary in itineraries.iter() {
+            println!();
+            println!("{}", Self::format_itinerary(date, itinerary));
+
+            for leg in itinerary.legs.iter() {
+                match leg {
+                    Leg::Ride {
+                        route,
+                        headsign,
+                        from,
+                        to,
+                        departure,
+                        arrival,
+                        ..
+                    } => println!(
+                        "  {}: {} {} - {} {}",
+                        match headsign {
+                            Some(headsign) => format!("{} → {}", route, headsign),
+                            None => route.clone(),
                         },
-                        additional
-                    );
+                        time(*departure),
+                        from,
+                        time(*arrival),
+                        to
+                    ),
+                    Leg::Walk { from, to, duration } => {
+                        println!("  walk {} min: {} - {}", duration.div_ceil(60), from, to)
+                    }
                 }
             }
         }
     }
 
-    #[cfg(feature = "prague")]
-    fn format_additionals(&self, record: &Record, additional: &mut String) {
-        if let Some(add) = record.additionals {
-            additional.push_str(add.to_string().as_str());
+    /// Formats time of the planned day (seconds from its midnight).
+    fn format_planned_time(date: NaiveDate, seconds: u32) -> String {
+        match date.and_hms_opt(0, 0, 0) {
+            Some(midnight) => {
+                Self::format_departure(midnight + chrono::Duration::seconds(seconds.into()))
+            }
+            None => String::new(),
         }
     }
 
+    /// Formats times and duration of the itinerary like:
+    /// 08:05 - 08:32 (27 min, 1 change)
+    /// Walking makes the arrival fall between whole minutes - it's rounded
+    /// up like durations of the walks so the total agrees with the legs.
+    fn format_itinerary(date: NaiveDate, itinerary: &Itinerary) -> String {
+        let departure = itinerary.departure / 60 * 60;
+        let arrival = itinerary.arrival.div_ceil(60) * 60;
+
+        format!(
+            "{} - {} ({} min, {} change{})",
+            Self::format_planned_time(date, departure),
+            Self::format_planned_time(date, arrival),
+            (arrival - departure) / 60,
+            itinerary.changes,
+            if itinerary.changes == 1 { "" } else { "s" }
+        )
+    }
+
+    /// Lets user choose one of the printed departures. Empty input means
+    /// no departure is chosen.
+    pub fn select_departure<'a>(
+        departures: &'a [Departure<'a>],
+    ) -> Result<Option<(&'a stop::Stop, &'a Record)>, Error> {
+        let records = departures
+            .iter()
+            .flat_map(|d| d.departures.iter().map(move |r| (d.stop, r)))
+            .collect::<Vec<(&stop::Stop, &Record)>>();
+
+        if records.is_empty() {
+            return Ok(None);
+        }
+
+        // 1. print departure choices.
+        println!();
+        for (i, (stop, record)) in records.iter().enumerate() {
+            let time = record
+                .departure()
+                .map(Self::format_departure)
+                .unwrap_or_default();
+            println!(
+                "{}) {}: {} {}",
+                i,
+                stop.name,
+                Self::format_route(record),
+                time
+            );
+        }
+
+        // 2. let user enter the number of a departure.
+        loop {
+            println!("Please enter the number of departure to expand (or nothing to quit):");
+            let mut number_input = String::new();
+            io::stdin().lock().read_line(&mut number_input)?;
+
+            if number_input.trim().is_empty() {
+                return Ok(None);
+            }
+
+            if let Ok(index) = number_input.trim().parse::<usize>() {
+                if let Some(&selected) = records.get(index) {
+                    return Ok(Some(selected));
+                }
+            }
+
+            println!("Wrong number! Try again.");
+        }
+    }
+
+    /// Formats stop heading like: Novovysočanská -> Sídliště Čakovice ♿
+    fn format_heading(departure: &Departure<'_>) -> String {
+        format!(
+            "{} -> {}{}{}",
+            departure.stop.name,
+            &departure.stop.terminating_stop,
+            Self::format_flag(departure.stop.wheelchair_boarding, " ♿"),
+            Self::format_unavailable(departure.realtime_unavailable)
+        )
+    }
+
+    /// Formats connection heading like: Novovysočanská => Palmovka
+    fn format_connection_heading(connection: &ConnectionDeparture<'_>) -> String {
+        format!(
+            "{} => {}{}",
+            connection.connection.from,
+            connection.connection.to,
+            Self::format_unavailable(connection.realtime_unavailable)
+        )
+    }
+
+    /// Marks headings of departures without realtime info (when it
+    /// couldn't be fetched).
+    fn format_unavailable(unavailable: bool) -> &'static str {
+        if unavailable {
+            " (realtime unavailable)"
+        } else {
+            ""
+        }
+    }
+
+    /// Formats one departure like:
+    /// 109 → Sídliště Čakovice 15:33 (in 10 min) ♿
+    fn format_record(&self, record: &Record) -> Option<String> {
+        let (departure, in_minutes) = Self::departure_time(record)?;
+
+        let additional = Self::format_realtime(record);
+
+        Some(format!(
+            "{} {} (in {} min){}{}",
+            Self::format_route(record),
+            Self::format_departure(departure),
+            in_minutes,
+            Self::format_accessibility(record),
+            additional
+        ))
+    }
+
+    /// Formats one departure briefly like: 109 15:33 (10 min)
+    fn format_compact(record: &Record) -> Option<String> {
+        let (departure, in_minutes) = Self::departure_time(record)?;
+
+        Some(format!(
+            "{} {} ({} min)",
+            record.route,
+            Self::format_departure(departure),
+            in_minutes
+        ))
+    }
+
+    /// Returns the (scheduled) departure date & time and minutes remaining
+    /// till the expected departure.
+    fn departure_time(record: &Record) -> Option<(NaiveDateTime, i64)> {
+        let departure = record.departure()?;
+        let expected = record.expected_departure()?;
+
+        Some((
+            departure,
+            (expected - Local::now().naive_local()).num_minutes(),
+        ))
+    }
+
+    /// Formats departure time with the day if it's not today like:
+    /// 13:20, tomorrow 05:12, Mon 05:12
+    fn format_departure(departure: NaiveDateTime) -> String {
+        let today = Local::now().naive_local().date();
+
+        match departure.date().signed_duration_since(today).num_days() {
+            0 => departure.format("%H:%M").to_string(),
+            1 => departure.format("tomorrow %H:%M").to_string(),
+            _ => departure.format("%a %H:%M").to_string(),
+        }
+    }
+
+    /// Formats route name with the trip destination (if known) like:
+    /// 136 → Sídliště Čakovice
+    fn format_route(record: &Record) -> String {
+        match record.headsign() {
+            Some(headsign) => format!("{} → {}", record.route, headsign),
+            None => format!("{} -", record.route),
+        }
+    }
+
+    /// Formats accessibility icons like: ♿🚲 (low-floor vehicle actually
+    /// serving the trip takes precedence over the timetable).
+    fn format_accessibility(record: &Record) -> String {
+        let low_floor = record
+            .realtime
+            .as_ref()
+            .and_then(|r| r.vehicle.as_ref()?.low_floor);
+        let icons = format!(
+            "{}{}",
+            Self::format_flag(low_floor.or(record.wheelchair_accessible), "♿"),
+            Self::format_flag(record.bikes_allowed, "🚲")
+        );
+
+        if icons.is_empty() {
+            icons
+        } else {
+            format!(" {}", icons)
+        }
+    }
+
+    /// Returns the icon only if the flag is known to be true.
+    fn format_flag(flag: Option<bool>, icon: &str) -> &str {
+        match flag {
+            Some(true) => icon,
+            _ => "",
+        }
+    }
+
+    /// Formats realtime info (if any) like: ❄ [13:22 +2 min]
+    fn format_realtime(record: &Record) -> String {
+        record
+            .realtime
+            .as_ref()
+            .map(|realtime| realtime.to_string())
+            .unwrap_or_default()
+    }
+
     /// Renders prompt with the given stops and prompt messages and let's
     /// user choose one stop which is then returned
-    pub fn select_stop<'a>(
-        prompt: &str,
-        stops: &'a [FoundStop],
-    ) -> Result<&'a FoundStop, Box<dyn std::error::Error>> {
+    pub fn select_stop<'a>(prompt: &str, stops: &'a [FoundStop]) -> Result<&'a FoundStop, Error> {
         // 1. print stop choices.
         for (i, stop) in stops.iter().enumerate() {
             Self::print_stop_record(i, stop);
@@ -404,6 +1392,37 @@ impl Ui {
         }
     }
 
+    /// Renders prompt with the given connections and lets user choose one
+    /// connection whose index is then returned.
+    pub fn select_connection(
+        prompt: &str,
+        connections: &[FoundConnection],
+    ) -> Result<usize, Error> {
+        if connections.is_empty() {
+            return Err(Error::Input("There are no connections.".to_string()));
+        }
+
+        // 1. print connection choices.
+        for (i, connection) in connections.iter().enumerate() {
+            println!("{}) {}", i, connection);
+        }
+
+        // 2. let user enter the number of a connection.
+        loop {
+            println!("{}", prompt);
+            let mut number_input = String::new();
+            io::stdin().lock().read_line(&mut number_input)?;
+
+            if let Ok(index) = number_input.trim().parse::<usize>() {
+                if index < connections.len() {
+                    return Ok(index);
+                }
+            }
+
+            println!("Wrong number! Try again.");
+        }
+    }
+
     /// Prints stop record as:
     /// 1) Stop -> TerminatingStop
     pub fn print_stop_record(number: usize, stop: &FoundStop) {
@@ -422,13 +1441,126 @@ impl Ui {
         println!(" (y/n)");
 
         let mut answer = String::new();
-        io::stdin().lock().read_line(&mut answer).unwrap();
-        answer = answer.trim().to_owned();
 
-        if "y" == answer.to_lowercase() {
-            return true;
+        // Unreadable input denies the request.
+        if io::stdin().lock().read_line(&mut answer).is_err() {
+            return false;
+        }
+
+        "y" == answer.trim().to_lowercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use chrono::Duration;
+    use trs::db::Database;
+
+    use super::*;
+
+    /// Provider recording stops of the enriched records.
+    #[derive(Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl RealtimeProvider for Recorder {
+        async fn enrich(&self, records: &mut [&mut Record]) -> Result<(), Error> {
+            let mut stops = self.0.lock().unwrap();
+            stops.extend(records.iter().map(|r| r.stop.clone()));
+
+            Ok(())
+        }
+    }
+
+    /// Stop with one departure tomorrow at noon.
+    fn stop(name: &str) -> stop::Stop {
+        let tomorrow = Local::now().naive_local().date() + Duration::days(1);
+
+        stop::Stop {
+            id: name.to_string(),
+            name: name.to_string(),
+            terminating_stop: "Palmovka".to_string(),
+            wheelchair_boarding: None,
+            database: Database {
+                records: vec![Record {
+                    route: "136".to_string(),
+                    route_id: None,
+                    trip: "service".to_string(),
+                    trip_id: name.to_string(),
+                    calendar: CustomCalendar::day(tomorrow),
+                    stop_time: Some(12 * 3600),
+                    stop: name.to_string(),
+                    stop_id: Some(name.to_string()),
+                    stop_sequence: None,
+                    trip_headsign: None,
+                    stop_headsign: None,
+                    wheelchair_accessible: None,
+                    bikes_allowed: None,
+                    destination_time: None,
+                    route_color: None,
+                    route_text_color: None,
+                    service_date: Some(tomorrow),
+                    realtime: None,
+                }],
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn status_bar_shows_first_stop_of_config() {
+        let stops = [stop("Zahradní"), stop("Alpha")];
+
+        for format in ["waybar", "line"] {
+            let recorder = Recorder::default();
+            let enriched = Arc::clone(&recorder.0);
+            let ui = Ui::new(args::app().get_matches_from(["trs", "-f", format]))
+                .unwrap()
+                .with_realtime(vec![Box::new(recorder)]);
+            let mut departures = stops
+                .iter()
+                .map(|stop| Departure {
+                    stop,
+                    departures: stop.database.records.clone(),
+                    alerts: vec![],
+                    realtime_unavailable: false,
+                })
+                .collect::<Vec<_>>();
+
+            ui.output(&mut departures, &mut []).await.unwrap();
+
+            assert_eq!(*enriched.lock().unwrap(), ["Zahradní"]);
+            assert_eq!(departures[0].stop.name, "Zahradní");
         }
+    }
+
+    #[test]
+    fn walk_only_itinerary() {
+        let itinerary = Itinerary {
+            departure: 9 * 3600,
+            arrival: 9 * 3600 + 89,
+            changes: 0,
+            legs: vec![Leg::Walk {
+                from: "Alpha".to_string(),
+                to: "Beta".to_string(),
+                duration: 89,
+            }],
+        };
+
+        assert_eq!(
+            Ui::format_itinerary(Local::now().naive_local().date(), &itinerary),
+            "09:00 - 09:02 (2 min, 0 changes)"
+        );
+    }
+
+    #[test]
+    fn footnote_marks() {
+        let marks = [0, 1, 25, 26, 27, 51, 52, 701, 702]
+            .map(Ui::footnote_mark)
+            .join(" ");
 
-        false
+        assert_eq!(marks, "a b z aa ab az ba zz aaa");
     }
 }